@@ -0,0 +1,371 @@
+//! Procedural macros for `jsonrpc2`, re-exported from there as
+//! `jsonrpc2::rpc`.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::punctuated::Punctuated;
+use syn::{Data, DeriveInput, Error, Expr, Fields, FnArg, Ident, ItemTrait, Lit, Meta, MetaNameValue, Pat, ReturnType,
+          Token, TraitItem, TraitItemFn, Type, UnOp};
+
+/// Generates the server and client sides of a JSON-RPC API from a trait.
+///
+/// ```ignore
+/// #[jsonrpc2::rpc]
+/// pub trait Math {
+///     fn add(&self, a: u64, b: u64) -> Result<u64, &'static str>;
+///     #[rpc(name = "math.neg")]
+///     fn neg(&self, a: i64) -> Result<i64, &'static str>;
+/// }
+/// ```
+///
+/// Next to the trait itself this expands to:
+///
+/// * `register_math(rpc: &JsonRpc, server: T)`, registering every
+///   method of `server`, which must be `Send + Sync`, on `rpc` under the
+///   method name or the name given with `#[rpc(name = "...")]`. Params may
+///   be passed by position or by argument name and are decoded with
+///   `rustc_serialize`.
+/// * `MathClient`, wrapping a `Client` with one typed method per trait
+///   method, which encodes the arguments and decodes the result.
+///
+/// Every method takes `&self` and returns a `Result`. Arguments and the
+/// success type must implement `rustc_serialize::{Decodable, Encodable}`.
+/// The error type must implement `Into<Error>` for the server and
+/// `From<ClientError>` for the client, as `&'static str` does. Params that
+/// do not decode are answered with `InvalidParams`.
+#[proc_macro_attribute]
+pub fn rpc(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        let span = proc_macro2::TokenStream::from(attr);
+        return Error::new_spanned(span, "#[rpc] takes no arguments").to_compile_error().into();
+    }
+    let mut item = syn::parse_macro_input!(item as ItemTrait);
+    match expand(&mut item) {
+        Ok(tokens) => tokens.into(),
+        Err(err)   => err.to_compile_error().into()
+    }
+}
+
+/// Implements `jsonrpc2::AppError` and `From<E> for jsonrpc2::Error` for an
+/// enum whose variants are annotated with their code and message.
+///
+/// ```ignore
+/// #[derive(Debug, AppError)]
+/// pub enum WalletError {
+///     #[app_error(code = 1, message = "Insufficient funds")]
+///     InsufficientFunds,
+///     #[app_error(code = 2, message = "Unknown account")]
+///     UnknownAccount(String),
+/// }
+/// ```
+///
+/// Variants are either units or hold one field, which is sent as the
+/// error's `data` and must implement `rustc_serialize::{Decodable,
+/// Encodable}`. Codes reserved by JSON-RPC (-32768 to -32000) are rejected.
+#[proc_macro_derive(AppError, attributes(app_error))]
+pub fn derive_app_error(item: TokenStream) -> TokenStream {
+    let item = syn::parse_macro_input!(item as DeriveInput);
+    match expand_app_error(&item) {
+        Ok(tokens) => tokens.into(),
+        Err(err)   => err.to_compile_error().into()
+    }
+}
+
+fn expand_app_error(item: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &item.ident;
+    let variants = match item.data {
+        Data::Enum(ref data) => &data.variants,
+        _ => return Err(Error::new_spanned(ident, "AppError can only be derived for enums"))
+    };
+    if !item.generics.params.is_empty() {
+        return Err(Error::new_spanned(&item.generics, "AppError can not be derived for generic enums"));
+    }
+
+    let mut codes = Vec::new();
+    let mut code_arms = Vec::new();
+    let mut message_arms = Vec::new();
+    let mut data_arms = Vec::new();
+    let mut from_error = Vec::new();
+    for variant in variants.iter() {
+        let name = &variant.ident;
+        let (code, message) = parse_app_error(variant)?;
+        let with_data = match variant.fields {
+            Fields::Unit => false,
+            Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => true,
+            _ => return Err(Error::new_spanned(variant, "AppError variants must be units or hold one field"))
+        };
+        codes.push(quote!((#code, #message)));
+        code_arms.push(quote!(#ident::#name { .. } => #code));
+        message_arms.push(quote!(#ident::#name { .. } => #message));
+        if with_data {
+            data_arms.push(quote!(#ident::#name(ref data) => ::jsonrpc2::codec::encode(data).ok()));
+            from_error.push(quote! {
+                if code == #code {
+                    let data = error.to_data().unwrap_or(::jsonrpc2::Json::Null);
+                    return ::jsonrpc2::codec::decode(data).ok().map(#ident::#name);
+                }
+            });
+        } else {
+            from_error.push(quote! {
+                if code == #code {
+                    return Some(#ident::#name);
+                }
+            });
+        }
+    }
+
+    Ok(quote! {
+        impl ::jsonrpc2::AppError for #ident {
+            fn codes() -> Vec<(i64, &'static str)> {
+                vec![#(#codes),*]
+            }
+            fn code(&self) -> i64 {
+                match *self {
+                    #(#code_arms,)*
+                }
+            }
+            fn message(&self) -> String {
+                let message: &'static str = match *self {
+                    #(#message_arms,)*
+                };
+                message.to_string()
+            }
+            #[allow(unreachable_patterns)]
+            fn data(&self) -> Option<::jsonrpc2::Json> {
+                match *self {
+                    #(#data_arms,)*
+                    _ => None
+                }
+            }
+            fn from_error(error: &::jsonrpc2::Error) -> Option<#ident> {
+                let code = error.to_i64();
+                #(#from_error)*
+                None
+            }
+        }
+
+        impl ::std::convert::From<#ident> for ::jsonrpc2::Error {
+            fn from(err: #ident) -> ::jsonrpc2::Error {
+                ::jsonrpc2::AppError::to_error(&err)
+            }
+        }
+    })
+}
+
+fn parse_app_error(variant: &syn::Variant) -> syn::Result<(i64, String)> {
+    let usage = "expected #[app_error(code = ..., message = \"...\")]";
+    let attr = match variant.attrs.iter().find(|attr| attr.path().is_ident("app_error")) {
+        Some(attr) => attr,
+        None       => return Err(Error::new_spanned(variant, usage))
+    };
+    let pairs = attr.parse_args_with(Punctuated::<MetaNameValue, Token![,]>::parse_terminated)?;
+    let mut code = None;
+    let mut message = None;
+    for pair in pairs.iter() {
+        if pair.path.is_ident("code") {
+            code = Some(parse_code(&pair.value)?);
+        } else if pair.path.is_ident("message") {
+            match pair.value {
+                Expr::Lit(ref lit) => match lit.lit {
+                    Lit::Str(ref lit) => message = Some(lit.value()),
+                    _ => return Err(Error::new_spanned(&pair.value, "expected a string"))
+                },
+                _ => return Err(Error::new_spanned(&pair.value, "expected a string"))
+            }
+        } else {
+            return Err(Error::new_spanned(pair, usage));
+        }
+    }
+    match (code, message) {
+        (Some(code), _) if (-32768 ..= -32000).contains(&code) => {
+            Err(Error::new_spanned(attr, "codes from -32768 to -32000 are reserved by JSON-RPC"))
+        },
+        (Some(code), Some(message)) => Ok((code, message)),
+        _ => Err(Error::new_spanned(attr, usage))
+    }
+}
+
+fn parse_code(expr: &Expr) -> syn::Result<i64> {
+    match *expr {
+        Expr::Lit(ref lit) => match lit.lit {
+            Lit::Int(ref int) => int.base10_parse::<i64>(),
+            _ => Err(Error::new_spanned(expr, "expected an integer"))
+        },
+        Expr::Unary(ref unary) => match unary.op {
+            UnOp::Neg(_) => parse_code(&unary.expr).map(|code| -code),
+            _            => Err(Error::new_spanned(expr, "expected an integer"))
+        },
+        _ => Err(Error::new_spanned(expr, "expected an integer"))
+    }
+}
+
+struct Method {
+    ident : Ident,
+    name  : String,
+    args  : Vec<(Ident, Type)>,
+    output: Type
+}
+
+fn expand(item: &mut ItemTrait) -> syn::Result<TokenStream2> {
+    let mut methods = Vec::new();
+    for trait_item in item.items.iter_mut() {
+        if let TraitItem::Fn(ref mut method) = *trait_item {
+            methods.push(parse_method(method)?);
+        }
+    }
+
+    let vis      = &item.vis;
+    let ident    = &item.ident;
+    let client   = Ident::new(&format!("{}Client", ident), ident.span());
+    let register = Ident::new(&format!("register_{}", snake_case(&ident.to_string())), ident.span());
+
+    let handles = methods.iter().map(|method| {
+        let ident = &method.ident;
+        let name  = &method.name;
+        let args  = method.args.iter().map(|(arg, _)| arg).collect::<Vec<_>>();
+        let decode = method.args.iter().enumerate().map(|(index, (arg, ty))| {
+            let arg_name = arg.to_string();
+            quote! {
+                let #arg: #ty = match ::jsonrpc2::codec::param(params, #index, #arg_name) {
+                    Ok(value) => value,
+                    Err(err)  => return Err(::jsonrpc2::Error::from(err))
+                };
+            }
+        });
+        quote! {
+            {
+                let server = server.clone();
+                rpc.register(#name, Box::new(move |params: &Option<::jsonrpc2::Json>| -> Result<::jsonrpc2::Json, ::jsonrpc2::Error> {
+                    #(#decode)*
+                    match server.#ident(#(#args),*) {
+                        Ok(result) => ::jsonrpc2::codec::encode(&result).map_err(::jsonrpc2::Error::from),
+                        Err(err)   => Err(::std::convert::Into::into(err))
+                    }
+                }));
+            }
+        }
+    });
+
+    let calls = methods.iter().map(|method| {
+        let ident  = &method.ident;
+        let name   = &method.name;
+        let output = &method.output;
+        let inputs = method.args.iter().map(|(arg, ty)| quote!(#arg: #ty));
+        let params = method.args.iter().map(|(arg, _)| quote! {
+            ::jsonrpc2::codec::encode(&#arg)
+                .map_err(|err| ::jsonrpc2::ClientError::Encode(err.to_string()))?
+        });
+        quote! {
+            #vis fn #ident(&self, #(#inputs),*) -> #output {
+                let params = ::jsonrpc2::Json::Array(vec![#(#params),*]);
+                let id     = self.client.next_id();
+                match self.client.request(#name, &Some(params), &id) {
+                    Ok(result) => ::jsonrpc2::codec::decode(result.unwrap_or(::jsonrpc2::Json::Null))
+                                      .map_err(|err| ::std::convert::From::from(::jsonrpc2::ClientError::Decode(err.to_string()))),
+                    Err(err)   => Err(::std::convert::From::from(err))
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        #item
+
+        /// Registers every method of `server` on `rpc`.
+        #vis fn #register<T: #ident + Send + Sync + 'static>(rpc: &::jsonrpc2::JsonRpc, server: T) {
+            let server = ::std::sync::Arc::new(server);
+            #(#handles)*
+        }
+
+        /// Typed client for the methods of the trait.
+        #vis struct #client {
+            client: ::jsonrpc2::Client
+        }
+
+        impl #client {
+            #vis fn new(client: ::jsonrpc2::Client) -> #client {
+                #client { client: client }
+            }
+            #vis fn client(&self) -> &::jsonrpc2::Client {
+                &self.client
+            }
+            #(#calls)*
+        }
+    })
+}
+
+fn parse_method(method: &mut TraitItemFn) -> syn::Result<Method> {
+    let mut name = method.sig.ident.to_string();
+    let mut attrs = Vec::new();
+    for attr in method.attrs.drain(..) {
+        if !attr.path().is_ident("rpc") {
+            attrs.push(attr);
+            continue;
+        }
+        let pair = match attr.meta {
+            Meta::List(ref list) => list.parse_args::<syn::MetaNameValue>()?,
+            _ => return Err(Error::new_spanned(&attr, "expected #[rpc(name = \"...\")]"))
+        };
+        match pair.value {
+            Expr::Lit(ref lit) if pair.path.is_ident("name") => match lit.lit {
+                Lit::Str(ref lit) => name = lit.value(),
+                _ => return Err(Error::new_spanned(&pair.value, "expected a string"))
+            },
+            _ => return Err(Error::new_spanned(&pair, "expected #[rpc(name = \"...\")]"))
+        }
+    }
+    method.attrs = attrs;
+
+    let sig = &method.sig;
+    let mut args = Vec::new();
+    for (index, input) in sig.inputs.iter().enumerate() {
+        match *input {
+            FnArg::Receiver(ref receiver) if index == 0 && receiver.reference.is_some()
+                                                        && receiver.mutability.is_none() => {},
+            FnArg::Typed(ref arg) if index > 0 => match *arg.pat {
+                Pat::Ident(ref pat) => args.push((pat.ident.clone(), (*arg.ty).clone())),
+                _ => return Err(Error::new_spanned(&arg.pat, "rpc arguments must be plain identifiers"))
+            },
+            _ => return Err(Error::new_spanned(input, "rpc methods must take `&self` first"))
+        }
+    }
+    if sig.inputs.is_empty() {
+        return Err(Error::new_spanned(sig, "rpc methods must take `&self`"));
+    }
+    let output = match sig.output {
+        ReturnType::Type(_, ref ty) => (**ty).clone(),
+        ReturnType::Default => return Err(Error::new_spanned(sig, "rpc methods must return a `Result`"))
+    };
+    Ok(Method { ident: sig.ident.clone(), name, args, output })
+}
+
+// A run of capitals is one word, its last capital starting the next word
+// if a lowercase letter follows: `HTTPApi` becomes `http_api`.
+fn snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<char>>();
+    let mut snake = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let prev = if index > 0 { Some(chars[index - 1]) } else { None };
+            let next = chars.get(index + 1);
+            let starts_word = match prev {
+                Some(prev) if prev.is_uppercase() => next.is_some_and(|next| next.is_lowercase()),
+                Some(prev)                        => prev != '_',
+                None                              => false
+            };
+            if starts_word {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
@@ -0,0 +1,233 @@
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use ::{Request, Response, Context, Json, ToJson};
+use middleware::{Middleware, Next};
+
+/// What redacted values are replaced with.
+pub const REDACTED: &'static str = "[REDACTED]";
+
+/// Applies the `(method, path)` redactions of `rules` that concern any of
+/// `names`, the names a call goes through, to its `params`; a method of `*`
+/// concerns every call.
+pub(crate) fn redact_params(params: &mut Json, names: &[String], rules: &[(String, String)]) {
+    for &(ref target, ref path) in rules {
+        if target == "*" || names.contains(target) {
+            redact(params, &path.split('.').collect::<Vec<&str>>());
+        }
+    }
+}
+
+/// How `AccessLog` writes its lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// One JSON object per line.
+    JsonLines,
+    /// `key=value` pairs, as read by logfmt parsers.
+    Logfmt
+}
+
+/// Formats `time` as RFC 3339 in UTC, with milliseconds.
+pub(crate) fn timestamp(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, secs) = ((elapsed.as_secs() / 86400) as i64, elapsed.as_secs() % 86400);
+    // Civil date of a day count, from Howard Hinnant's `civil_from_days`.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day,
+            secs / 3600, secs / 60 % 60, secs % 60, elapsed.subsec_nanos() / 1_000_000)
+}
+
+/// Replaces the value at `path` in `json` with `REDACTED`. Path segments
+/// name object members or array indices, and `*` stands for any of them.
+fn redact(json: &mut Json, path: &[&str]) {
+    let (segment, rest) = match path.split_first() {
+        Some(split) => split,
+        None        => {
+            *json = REDACTED.to_json();
+            return;
+        }
+    };
+    match *json {
+        Json::Object(ref mut object) => {
+            for (key, value) in object.iter_mut() {
+                if *segment == "*" || key == segment {
+                    redact(value, rest);
+                }
+            }
+        },
+        Json::Array(ref mut array) => {
+            for (i, value) in array.iter_mut().enumerate() {
+                if *segment == "*" || i.to_string() == *segment {
+                    redact(value, rest);
+                }
+            }
+        },
+        _ => {}
+    }
+}
+
+/// Quotes values that need it, escaping every control character so that a
+/// value can neither end the line nor hide what follows it.
+fn logfmt_value(value: &str) -> String {
+    if !(value.is_empty() || value.contains(|c: char| c == ' ' || c == '"' || c == '=' || c.is_control())) {
+        return value.to_string();
+    }
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\'                => quoted.push_str("\\\\"),
+            '"'                 => quoted.push_str("\\\""),
+            '\n'                => quoted.push_str("\\n"),
+            '\r'                => quoted.push_str("\\r"),
+            '\t'                => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{{{:04x}}}", c as u32)),
+            c                   => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Middleware writing one structured line per request, so that handlers
+/// need not log calls themselves.
+///
+/// ```ignore
+/// rpc.wrap(Box::new(AccessLog::new(LogFormat::Logfmt)
+///                       .params(true)
+///                       .redact("auth.login", "password")
+///                       .redact("*", "token")));
+/// ```
+///
+/// Each line holds the time the request arrived, the peer of the call
+/// `Context`, the method, the id, the size of the params in bytes, the
+/// latency in milliseconds and the outcome: `result`, or `error` with its
+/// code. With `params`, the params are included too, with the values
+/// registered with `redact` replaced by `REDACTED`.
+///
+/// Lines go to the `log` crate at info level under the `jsonrpc2::access`
+/// target, or to the writer given with `writer`. Add the layer first so
+/// that the latency covers the other middleware, and requests they reject
+/// are logged too.
+pub struct AccessLog {
+    format : LogFormat,
+    params : bool,
+    redact : Vec<(String, String)>,
+    writer : Option<Mutex<Box<dyn Write + Send>>>
+}
+
+impl AccessLog {
+    pub fn new(format: LogFormat) -> AccessLog {
+        AccessLog { format: format, params: false, redact: Vec::new(), writer: None }
+    }
+    /// Includes the params of each request in its line.
+    pub fn params(mut self, enabled: bool) -> AccessLog {
+        self.params = enabled;
+        self
+    }
+    /// Redacts the params at `path` of `method`, or of every method if
+    /// `method` is `*`. Calls reaching `method` through an alias or a
+    /// fallback are redacted too. The path is a dotted list of member names and array
+    /// indices, e.g. `password`, `0` for the first positional param, or
+    /// `accounts.*.pin`.
+    pub fn redact(mut self, method: &str, path: &str) -> AccessLog {
+        self.redact.push((method.to_string(), path.to_string()));
+        self
+    }
+    /// Writes the lines to `writer` instead of the log.
+    pub fn writer(mut self, writer: Box<dyn Write + Send>) -> AccessLog {
+        self.writer = Some(Mutex::new(writer));
+        self
+    }
+    /// The params of `request` as logged, with the redactions of every name
+    /// in its `route` applied; see `JsonRpc::route`.
+    pub fn redacted(&self, request: &Request, route: &[String]) -> Option<Json> {
+        let mut params = request.params();
+        if let Some(ref mut params) = params {
+            redact_params(params, route, &self.redact);
+        }
+        params
+    }
+    fn line(&self, time: SystemTime, request: &Request, route: &[String], ctx: &Context, latency: f64, response: &Response) -> String {
+        let code = match *response {
+            Response::Error{ ref error, .. } => Some(error.to_i64()),
+            _                                => None
+        };
+        let params_bytes = request.params().map(|params| params.to_string().len()).unwrap_or(0);
+        let params = match self.params {
+            true  => self.redacted(request, route),
+            false => None
+        };
+        match self.format {
+            LogFormat::JsonLines => {
+                let mut line = BTreeMap::new();
+                line.insert("ts".to_string(),           timestamp(time).to_json());
+                line.insert("peer".to_string(),         ctx.peer().map(|peer| peer.to_string()).to_json());
+                line.insert("method".to_string(),       request.method().to_json());
+                line.insert("id".to_string(),           request.id().to_json());
+                line.insert("params_bytes".to_string(), params_bytes.to_json());
+                line.insert("latency_ms".to_string(),   latency.to_json());
+                line.insert("outcome".to_string(),      if code.is_some() { "error" } else { "result" }.to_json());
+                if let Some(code) = code {
+                    line.insert("code".to_string(), code.to_json());
+                }
+                if let Some(params) = params {
+                    line.insert("params".to_string(), params);
+                }
+                Json::Object(line).to_string()
+            },
+            LogFormat::Logfmt => {
+                let mut line = vec![
+                    ("ts",           timestamp(time)),
+                    ("peer",         ctx.peer().unwrap_or("").to_string()),
+                    ("method",       request.method()),
+                    ("id",           request.id().map(|id| id.to_string()).unwrap_or("".to_string())),
+                    ("params_bytes", params_bytes.to_string()),
+                    ("latency_ms",   format!("{:.3}", latency)),
+                    ("outcome",      if code.is_some() { "error" } else { "result" }.to_string())
+                ];
+                if let Some(code) = code {
+                    line.push(("code", code.to_string()));
+                }
+                if let Some(params) = params {
+                    line.push(("params", params.to_string()));
+                }
+                line.iter()
+                    .map(|&(key, ref value)| format!("{}={}", key, logfmt_value(value)))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            }
+        }
+    }
+}
+
+impl Middleware for AccessLog {
+    fn handle(&self, request: Request, ctx: &mut Context, next: Next) -> Response {
+        let (time, start) = (SystemTime::now(), Instant::now());
+        let route = next.rpc().route(&request.method());
+        let response = next.run(request.clone(), ctx);
+        let elapsed = start.elapsed();
+        let latency = elapsed.as_secs() as f64 * 1e3 + elapsed.subsec_nanos() as f64 / 1e6;
+        let line = self.line(time, &request, &route, ctx, latency, &response);
+        match self.writer {
+            Some(ref writer) => {
+                let mut writer = writer.lock().unwrap();
+                if let Err(err) = writeln!(writer, "{}", line) {
+                    warn!("could not write the access log: {}", err);
+                }
+            },
+            None => info!(target: "jsonrpc2::access", "{}", line)
+        }
+        response
+    }
+}
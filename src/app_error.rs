@@ -0,0 +1,76 @@
+
+use std::collections::BTreeMap;
+use ::{JsonRpc, Error, Json};
+
+/// Whether `code` lies in the range the JSON-RPC 2.0 spec reserves for
+/// predefined and server errors (-32768 to -32000).
+pub fn is_reserved(code: i64) -> bool {
+    code >= -32768 && code <= -32000
+}
+
+/// An error type of the application, with codes and messages of its own.
+///
+/// Usually derived, which also implements `From<E> for Error` so handlers
+/// can fail with `E` directly:
+///
+/// ```ignore
+/// #[derive(Debug, AppError)]
+/// pub enum WalletError {
+///     #[app_error(code = 1, message = "Insufficient funds")]
+///     InsufficientFunds,
+///     #[app_error(code = 2, message = "Unknown account")]
+///     UnknownAccount(String),
+/// }
+/// ```
+///
+/// The field of a single-field variant travels as the error's `data`.
+pub trait AppError: Sized {
+    /// Every code of the type with its message.
+    fn codes() -> Vec<(i64, &'static str)>;
+    fn code(&self) -> i64;
+    fn message(&self) -> String;
+    fn data(&self) -> Option<Json> {
+        None
+    }
+    /// Rebuilds the error from an `Error`, e.g. one returned by a remote
+    /// server; `None` when its code is not one of `codes`.
+    fn from_error(error: &Error) -> Option<Self>;
+    fn to_error(&self) -> Error {
+        Error::new(self.code(), &self.message(), self.data())
+    }
+}
+
+impl JsonRpc {
+    /// Declares the error codes of `E`, which are then listed in the
+    /// OpenRPC document.
+    ///
+    /// Codes in the reserved range and codes already declared with another
+    /// message are rejected; nothing is declared then and the rejected codes
+    /// are returned instead.
+    pub fn register_errors<E: AppError>(&mut self) -> Result<(), Vec<i64>> {
+        let mut errors = self.errors.clone();
+        let mut rejected = Vec::new();
+        for (code, message) in E::codes() {
+            if is_reserved(code) || errors.get(&code).map_or(false, |known| known != message) {
+                rejected.push(code);
+            } else {
+                errors.insert(code, message.to_string());
+            }
+        }
+        if !rejected.is_empty() {
+            return Err(rejected);
+        }
+        self.errors = errors;
+        Ok(())
+    }
+    /// The codes of `errors` that `register_errors` would reject.
+    pub(crate) fn conflicting_errors(&self, errors: &BTreeMap<i64, String>) -> Vec<i64> {
+        errors.iter()
+              .filter(|&(code, message)| is_reserved(*code) || self.errors.get(code).map_or(false, |known| known != message))
+              .map(|(code, _)| *code)
+              .collect()
+    }
+    pub fn errors(&self) -> &BTreeMap<i64, String> {
+        &self.errors
+    }
+}
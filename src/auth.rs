@@ -0,0 +1,276 @@
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str;
+use std::sync::{Arc, Mutex};
+use getrandom::getrandom;
+use rustc_serialize::base64::FromBase64;
+use rustc_serialize::hex::ToHex;
+use ::{JsonRpc, Request, Response, Context, Error, Json, ToJson};
+use codec;
+use middleware::{Middleware, Next};
+
+/// Code of the error for calls that need an identity but have none.
+pub const UNAUTHENTICATED_CODE: i64 = -32001;
+
+/// Code of the error for calls whose identity lacks a required role or
+/// scope.
+pub const FORBIDDEN_CODE: i64 = -32003;
+
+/// Credentials presented by a caller.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credentials {
+    Bearer(String),
+    Basic{ user: String, password: String }
+}
+
+impl Credentials {
+    /// Parses the value of an `Authorization` header, e.g. `Bearer abc`.
+    pub fn from_header(value: &str) -> Option<Credentials> {
+        let mut parts = value.trim().splitn(2, ' ');
+        let scheme = parts.next().unwrap_or("").to_lowercase();
+        let value  = parts.next().unwrap_or("").trim();
+        match (scheme.as_ref(), value) {
+            (_, "") => None,
+            ("bearer", token) => Some(Credentials::Bearer(token.to_string())),
+            ("basic", encoded) => {
+                let decoded = match encoded.from_base64() {
+                    Ok(decoded) => decoded,
+                    Err(_)      => return None
+                };
+                let decoded = match str::from_utf8(&decoded) {
+                    Ok(decoded) => decoded.to_string(),
+                    Err(_)      => return None
+                };
+                let mut parts = decoded.splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(user), Some(password)) => Some(Credentials::Basic{
+                        user    : user.to_string(),
+                        password: password.to_string()
+                    }),
+                    _ => None
+                }
+            },
+            _ => None
+        }
+    }
+    /// Finds the `Authorization` header among the headers of an HTTP request
+    /// or a WebSocket handshake, given as name and value pairs.
+    pub fn from_headers<'a, I>(headers: I) -> Option<Credentials> where I: IntoIterator<Item=(&'a str, &'a str)> {
+        headers.into_iter()
+               .find(|&(name, _)| name.eq_ignore_ascii_case("authorization"))
+               .and_then(|(_, value)| Credentials::from_header(value))
+    }
+}
+
+/// Who is calling, with what they are allowed to do.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Identity {
+    subject: String,
+    roles  : BTreeSet<String>,
+    scopes : BTreeSet<String>
+}
+
+impl Identity {
+    pub fn new(subject: &str) -> Identity {
+        Identity { subject: subject.to_string(), ..Identity::default() }
+    }
+    pub fn role(mut self, role: &str) -> Identity {
+        self.roles.insert(role.to_string());
+        self
+    }
+    pub fn scope(mut self, scope: &str) -> Identity {
+        self.scopes.insert(scope.to_string());
+        self
+    }
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.contains(role)
+    }
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.contains(scope)
+    }
+}
+
+/// What a method demands of its callers; see `Auth::require`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Requirement {
+    /// Any identity will do.
+    Authenticated,
+    Role(String),
+    Scope(String)
+}
+
+impl Requirement {
+    pub fn role(role: &str) -> Requirement {
+        Requirement::Role(role.to_string())
+    }
+    pub fn scope(scope: &str) -> Requirement {
+        Requirement::Scope(scope.to_string())
+    }
+    pub fn allows(&self, identity: &Identity) -> bool {
+        match *self {
+            Requirement::Authenticated   => true,
+            Requirement::Role(ref role)   => identity.has_role(role),
+            Requirement::Scope(ref scope) => identity.has_scope(scope)
+        }
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Requirement::Authenticated   => write!(f, "authenticated"),
+            Requirement::Role(ref role)   => write!(f, "role {}", role),
+            Requirement::Scope(ref scope) => write!(f, "scope {}", scope)
+        }
+    }
+}
+
+/// Turns credentials into an identity, or `None` if they are not valid.
+pub trait Authenticator: Send + Sync {
+    fn authenticate(&self, credentials: &Credentials) -> Option<Identity>;
+}
+
+impl<F> Authenticator for F where F: Fn(&Credentials) -> Option<Identity> + Send + Sync {
+    fn authenticate(&self, credentials: &Credentials) -> Option<Identity> {
+        self(credentials)
+    }
+}
+
+/// Bearer tokens handed out by a login method; see
+/// `JsonRpc::register_login`.
+#[derive(Debug, Default)]
+pub struct Sessions {
+    tokens: Mutex<BTreeMap<String, Identity>>
+}
+
+impl Sessions {
+    pub fn new() -> Sessions {
+        Sessions::default()
+    }
+    /// Starts a session for `identity` and returns its token: 256 bits
+    /// from the randomness of the operating system, hex encoded.
+    ///
+    /// Panics if the operating system has no randomness to give.
+    pub fn login(&self, identity: Identity) -> String {
+        let mut bytes = [0u8; 32];
+        getrandom(&mut bytes).expect("no randomness for session tokens");
+        let token = bytes.to_hex();
+        self.tokens.lock().unwrap().insert(token.clone(), identity);
+        token
+    }
+    /// Ends the session of `token`; returns whether there was one.
+    pub fn logout(&self, token: &str) -> bool {
+        self.tokens.lock().unwrap().remove(token).is_some()
+    }
+}
+
+impl Authenticator for Sessions {
+    fn authenticate(&self, credentials: &Credentials) -> Option<Identity> {
+        match *credentials {
+            Credentials::Bearer(ref token) => self.tokens.lock().unwrap().get(token).cloned(),
+            _                              => None
+        }
+    }
+}
+
+fn unauthenticated() -> Error {
+    Error::new(UNAUTHENTICATED_CODE, "Unauthenticated", None)
+}
+
+/// Middleware authenticating callers and enforcing what methods require.
+///
+/// ```ignore
+/// rpc.wrap(Box::new(Auth::new(Box::new(check_token))
+///                       .require("admin.", Requirement::role("admin"))
+///                       .require("report.", Requirement::scope("reports"))));
+/// ```
+///
+/// Transports put the caller's credentials in the call `Context`, e.g.
+/// `HttpHandler` from the `Authorization` header. The identity they stand
+/// for is stored in the context for handlers and later middleware. Calls
+/// of a method with requirements fail with `UNAUTHENTICATED_CODE` when
+/// there is no valid identity, and with `FORBIDDEN_CODE` when it does not
+/// meet every requirement; other methods are open to anyone. Calls through
+/// an alias or a fallback also have to meet the requirements of the method
+/// serving them.
+pub struct Auth {
+    authenticator: Box<dyn Authenticator>,
+    rules        : Vec<(String, Requirement)>
+}
+
+impl Auth {
+    pub fn new(authenticator: Box<dyn Authenticator>) -> Auth {
+        Auth { authenticator: authenticator, rules: Vec::new() }
+    }
+    /// Adds a requirement for the methods starting with `prefix`.
+    pub fn require(mut self, prefix: &str, requirement: Requirement) -> Auth {
+        self.rules.push((prefix.to_string(), requirement));
+        self
+    }
+    /// The requirements of `method`.
+    pub fn requirements(&self, method: &str) -> Vec<&Requirement> {
+        self.rules.iter()
+                  .filter(|&&(ref prefix, _)| method.starts_with(prefix.as_str()))
+                  .map(|&(_, ref requirement)| requirement)
+                  .collect()
+    }
+}
+
+impl Middleware for Auth {
+    fn handle(&self, request: Request, ctx: &mut Context, next: Next) -> Response {
+        if ctx.identity().is_none() {
+            let identity = ctx.credentials().and_then(|credentials| self.authenticator.authenticate(credentials));
+            if let Some(identity) = identity {
+                ctx.set_identity(identity);
+            }
+        }
+        let method = request.method();
+        let target = next.rpc().resolve(&method).map(|(target, _)| target);
+        let error = {
+            let mut requirements = self.requirements(&method);
+            if let Some(ref target) = target {
+                for requirement in self.requirements(target) {
+                    if !requirements.contains(&requirement) {
+                        requirements.push(requirement);
+                    }
+                }
+            }
+            match ctx.identity() {
+                _ if requirements.is_empty() => None,
+                None => Some(unauthenticated()),
+                Some(identity) => requirements.iter().find(|requirement| !requirement.allows(identity)).map(|requirement| {
+                    warn!("{} denied to {}: {} required", method, identity.subject(), requirement);
+                    Error::new(FORBIDDEN_CODE, "Forbidden", Some(requirement.to_string().to_json()))
+                })
+            }
+        };
+        match error {
+            Some(error) => Response::Error{ jsonrpc: request.jsonrpc(), error: error, id: request.id() },
+            None        => next.run(request, ctx)
+        }
+    }
+}
+
+impl JsonRpc {
+    /// Registers a login method taking a `user` and a `password`, by name
+    /// or position. Valid ones, as judged by `authenticator` given Basic
+    /// credentials, start a session and the method returns its token,
+    /// which callers then send as a Bearer token. Others fail with
+    /// `UNAUTHENTICATED_CODE`.
+    pub fn register_login(&self, method: &str, sessions: Arc<Sessions>, authenticator: Box<dyn Authenticator>) {
+        self.register(method, Box::new(move |params: &Option<Json>| -> Result<Json, Error> {
+            let credentials = Credentials::Basic{
+                user    : codec::param(params, 0, "user")?,
+                password: codec::param(params, 1, "password")?
+            };
+            match authenticator.authenticate(&credentials) {
+                Some(identity) => Ok(sessions.login(identity).to_json()),
+                None           => Err(unauthenticated())
+            }
+        }));
+    }
+}
@@ -1,63 +1,306 @@
 extern crate url;
 extern crate hyper;
 
+use std::error;
+use std::fmt;
 use std::str;
 use std::string::ToString;
 use std::io::Read;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Instant, SystemTime};
 use self::url::{Url, ParseError};
 use self::hyper::client::{Client as HyperClient, IntoUrl};
-use self::hyper::header::ContentType;
-use ::{Request, Response, Json, ToJson, Error};
+use self::hyper::header::{ContentType, Headers};
+use ::{JsonRpc, Request, Response, ResponseError, Json, Mode, Version, Id, Error, AppError, Recorder, Exchange, Side};
+#[cfg(feature = "metrics")]
+use ::Metrics;
+use ::TRACEPARENT;
+#[cfg(feature = "tracing")]
+use trace;
 
+/// Why a call made with `Client::request` failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientError {
+    /// The request could not be sent.
+    Request(String),
+    /// The response could not be read.
+    Read(String),
+    /// The response is not a valid JSON-RPC response.
+    Response(ResponseError),
+    /// The server answered with an error.
+    Remote(Error),
+    /// The params could not be encoded.
+    Encode(String),
+    /// The result could not be decoded into the expected type.
+    Decode(String)
+}
+
+impl ClientError {
+    /// The remote error as an application error, if the server answered
+    /// with one of the codes of `E`.
+    pub fn app_error<E: AppError>(&self) -> Option<E> {
+        match *self {
+            ClientError::Remote(ref error) => E::from_error(error),
+            _                              => None
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClientError::Request(ref err)  => write!(f, "request failed: {}", err),
+            ClientError::Read(ref err)     => write!(f, "could not read the response: {}", err),
+            ClientError::Response(ref err) => write!(f, "invalid response: {}", err),
+            ClientError::Remote(ref err)   => write!(f, "remote error: {}", err),
+            ClientError::Encode(ref err)   => write!(f, "could not encode the params: {}", err),
+            ClientError::Decode(ref err)   => write!(f, "could not decode the result: {}", err)
+        }
+    }
+}
+
+impl error::Error for ClientError {}
+
+/// The messages `Client::call` has always failed with.
+impl From<ClientError> for &'static str {
+    fn from(err: ClientError) -> &'static str {
+        match err {
+            ClientError::Request(_)  => "RPC Request Failed.",
+            ClientError::Read(_)     => "RPC Response Read Fail.",
+            ClientError::Response(_) => "RPC Response Parse Fail.",
+            ClientError::Remote(_)   => "RPC Response Error.",
+            ClientError::Encode(_)   => "RPC Params Encode Fail.",
+            ClientError::Decode(_)   => "RPC Result Decode Fail."
+        }
+    }
+}
+
+impl From<ClientError> for String {
+    fn from(err: ClientError) -> String {
+        err.to_string()
+    }
+}
+
+/// Carries the requests of a `Client` that does not talk HTTP, e.g. to a
+/// `JsonRpc` in the same process or over a custom protocol.
+pub trait Transport: Send + Sync {
+    /// Sends a request body and returns the response body, empty if there
+    /// is none.
+    fn send(&self, body: &str) -> Result<Vec<u8>, ClientError>;
+}
+
+/// Calls are handled in memory, with a fresh `Context` each.
+impl Transport for JsonRpc {
+    fn send(&self, body: &str) -> Result<Vec<u8>, ClientError> {
+        Ok(self.handle(body).unwrap_or_default().into_bytes())
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn send(&self, body: &str) -> Result<Vec<u8>, ClientError> {
+        (**self).send(body)
+    }
+}
 
 pub struct Client {
-    uri: String
+    uri: String,
+    transport: Option<Box<dyn Transport>>,
+    ids: AtomicUsize,
+    version: Version,
+    mode: Mode,
+    authorization: Option<String>,
+    recorder: Option<Arc<Recorder>>,
+    #[cfg(feature = "metrics")]
+    metrics: Arc<Metrics>
 }
 
 impl Client {
     pub fn new(url: &str) -> Result<Client, &'static str> {
-        Ok(Client {uri: url.to_string()})
+        Ok(Client {
+            uri: url.to_string(),
+            transport: None,
+            ids: AtomicUsize::new(1),
+            version: Version::V2,
+            mode: Mode::Lenient,
+            authorization: None,
+            recorder: None,
+            #[cfg(feature = "metrics")]
+            metrics: Arc::new(Metrics::new())
+        })
+    }
+    /// A client sending its requests with `transport` instead of HTTP.
+    ///
+    /// ```ignore
+    /// let client = Client::with_transport(Box::new(Arc::new(rpc)));
+    /// ```
+    ///
+    /// Without headers, the `Authorization` is not sent, and the trace
+    /// context travels in the `traceparent` member of the request.
+    pub fn with_transport(transport: Box<dyn Transport>) -> Client {
+        let mut client = Client::new("").unwrap();
+        client.transport = Some(transport);
+        client
+    }
+    /// The metrics of the calls made; see `Metrics`.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+    /// Records into `metrics` instead, e.g. to share them with other
+    /// clients or with a `JsonRpc`.
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = metrics;
+    }
+    /// Sends `value` as the `Authorization` header of every request, e.g.
+    /// `Bearer <token>`.
+    pub fn set_authorization(&mut self, value: &str) {
+        self.authorization = Some(value.to_string());
+    }
+    /// Records every request sent, and the response to it, with
+    /// `recorder`; see `Recorder`.
+    pub fn record(&mut self, recorder: Arc<Recorder>) {
+        self.recorder = Some(recorder);
+    }
+    /// Sets the protocol version of the requests sent; `Version::V2` by
+    /// default. Use `Version::V1` to talk to JSON-RPC 1.0 services.
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+    pub fn version(&self) -> Version {
+        self.version
+    }
+    /// Sets how strictly 2.0 responses are parsed; `Mode::Lenient` by
+    /// default, logging each deviation from the spec it accepts. Use
+    /// `Mode::Strict` to reject them instead.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+    fn parse(&self, method: &str, body: &[u8]) -> Result<Response, ResponseError> {
+        let json = match str::from_utf8(body) {
+            Ok(body) => Json::from_str(body).map_err(|err| ResponseError::Syntax(err.to_string()))?,
+            Err(err) => return Err(ResponseError::Syntax(err.to_string()))
+        };
+        if self.version == Version::V1 {
+            return Response::parse_v1(json);
+        }
+        let (response, deviations) = Response::inspect(json, self.mode)?;
+        for deviation in deviations {
+            warn!("response to {} from {}: {}", method, self.target().unwrap_or("the transport"), deviation);
+        }
+        Ok(response)
+    }
+    /// A fresh request id, counting up from 1.
+    pub fn next_id(&self) -> i64 {
+        self.ids.fetch_add(1, Ordering::SeqCst) as i64
     }
     pub fn call(&self, method: &str, params: &Option<Json>, id: &i64) -> Result<Option<Json>, &'static str> {
-        let request = Request::new("2.0", method, Some(params.to_json()), Some(id.clone()));
+        self.request(method, params, id).map_err(From::from)
+    }
+    /// Where requests go, for logs and recordings.
+    fn target(&self) -> Option<&str> {
+        match self.transport {
+            Some(_) => None,
+            None    => Some(&self.uri)
+        }
+    }
+    /// Sends a request body and returns the response body.
+    fn send(&self, body: &str, traceparent: Option<String>) -> Result<Vec<u8>, ClientError> {
+        if let Some(ref transport) = self.transport {
+            return transport.send(body);
+        }
+        let url = self.uri.into_url().map_err(|err| ClientError::Request(err.to_string()))?;
         let client  = HyperClient::new();
-        let req = client.put(self.uri.into_url().unwrap()).header(ContentType::json())
-                    .body(request.to_string().as_bytes())
-                    .send();
-        match req {
-            Ok(mut response) => {
-                let mut body: Vec<u8> = Vec::new();
-                match response.read_to_end(&mut body) {
-                    Ok(size) => match Json::from_str(str::from_utf8(&body).unwrap()) {
-                        Ok(json) => {
-                            match json.as_object().unwrap().get("result"){
-                                Some(result) => {
-                                    if result.is_null() {
-                                        Ok(None)
-                                    } else if result.is_object() 
-                                            || result.is_array()
-                                            || result.is_u64()
-                                            || result.is_i64()
-                                            || result.is_f64()
-                                            || result.is_string()
-                                            || result.is_boolean() {
-                                        Ok(Some(result.clone()))
-                                    } else {
-                                        Ok(None)
-                                    }
-                                },
-                                None => Ok(None)
-                            }
-                        },
-                        Err(_)   => Err("RPC Response To Json Fail.")
-                    },
-                    Err(_)   => Err("RPC Response Read Fail.")
-                }
+        let mut headers = Headers::new();
+        headers.set(ContentType::json());
+        if let Some(traceparent) = traceparent {
+            headers.set_raw(TRACEPARENT, vec![traceparent.into_bytes()]);
+        }
+        if let Some(ref authorization) = self.authorization {
+            headers.set_raw("Authorization", vec![authorization.clone().into_bytes()]);
+        }
+        let mut response = client.put(url).headers(headers)
+                                 .body(body.as_bytes())
+                                 .send()
+                                 .map_err(|err| ClientError::Request(err.to_string()))?;
+        let mut received = Vec::new();
+        response.read_to_end(&mut received).map_err(|err| ClientError::Read(err.to_string()))?;
+        Ok(received)
+    }
+    /// Sends a notification of `method`, which the server does not answer:
+    /// without an `id` in 2.0, with `"id": null` in 1.0. Whatever comes
+    /// back is not read as a response.
+    pub fn notify(&self, method: &str, params: &Option<Json>) -> Result<(), ClientError> {
+        let sent = Request::notification(self.version.as_str(), method, params.clone()).to_string();
+        let (time, start) = (SystemTime::now(), Instant::now());
+        let received = self.send(&sent, None);
+        if let Some(ref recorder) = self.recorder {
+            recorder.record(&Exchange::new(Side::Client, self.target(), time, start.elapsed(), &sent, None));
+        }
+        received.map(|_| ())
+    }
+    /// Like `call`, failing with the reason; a remote error can be turned
+    /// into an application error with `ClientError::app_error`.
+    pub fn request(&self, method: &str, params: &Option<Json>, id: &i64) -> Result<Option<Json>, ClientError> {
+        #[allow(unused_mut)]
+        let mut request = Request::new(self.version.as_str(), method, params.clone(), Some(Id::Number(*id)));
+        #[cfg(feature = "tracing")]
+        let (span, trace) = trace::send(method, *id);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        // Without headers, the trace context travels in the request.
+        #[cfg(feature = "tracing")]
+        let traceparent = match self.transport {
+            Some(_) => {
+                request.set_trace(Some(trace.clone()));
+                None
             },
-            Err(_)           => Err("RPC Request Failed.")
+            None => Some(trace.to_string())
+        };
+        #[cfg(not(feature = "tracing"))]
+        let traceparent = None;
+        #[cfg(feature = "metrics")]
+        let call = self.metrics.send(method, &request);
+        let (time, start) = (SystemTime::now(), Instant::now());
+        let sent = request.to_string();
+        let received = self.send(&sent, traceparent);
+        let result = match received {
+            Ok(ref body) => self.parse(method, body).map_err(ClientError::Response),
+            Err(ref err) => Err(err.clone())
+        };
+        if let Some(ref recorder) = self.recorder {
+            let received = received.as_ref().ok().map(|body| String::from_utf8_lossy(body).into_owned());
+            recorder.record(&Exchange::new(Side::Client, self.target(), time, start.elapsed(),
+                                           &sent, received.as_ref().map(|received| received.as_str())));
+        }
+        #[cfg(feature = "metrics")]
+        match result {
+            Ok(ref response) => call.respond(response, received.as_ref().map(|body| body.len()).unwrap_or(0)),
+            Err(ClientError::Request(_)) => call.fail("request"),
+            Err(ClientError::Read(_))    => call.fail("read"),
+            Err(_)                       => call.fail("response")
+        }
+        #[cfg(feature = "tracing")]
+        match result {
+            Ok(ref response) => trace::respond(&span, response),
+            Err(ClientError::Request(_)) => trace::fail(&span, "request"),
+            Err(ClientError::Read(_))    => trace::fail(&span, "read"),
+            Err(_)                       => trace::fail(&span, "response")
+        }
+        let target = self.target().unwrap_or("the transport");
+        match result {
+            Ok(Response::Success{ result, .. }) => Ok(result),
+            Ok(Response::Error{ error, .. }) => {
+                debug!("{} from {} failed: {}", method, target, error);
+                Err(ClientError::Remote(error))
+            },
+            Err(ClientError::Response(err)) => {
+                warn!("invalid response to {} from {}: {}", method, target, err);
+                Err(ClientError::Response(err))
+            },
+            Err(err) => Err(err)
         }
     }
 }
-
-
@@ -0,0 +1,29 @@
+
+use rustc_serialize::{Decodable, Encodable};
+use rustc_serialize::json::{self, Decoder, DecoderError, EncoderError};
+use ::Json;
+
+/// Decodes a `Json` value into any `RustcDecodable` type.
+pub fn decode<T: Decodable>(json: Json) -> Result<T, DecoderError> {
+    let mut decoder = Decoder::new(json);
+    T::decode(&mut decoder)
+}
+
+/// Encodes any `RustcEncodable` value as `Json`.
+pub fn encode<T: Encodable>(value: &T) -> Result<Json, EncoderError> {
+    let text = json::encode(value)?;
+    // The encoder only ever writes valid JSON.
+    Ok(Json::from_str(&text).unwrap())
+}
+
+/// Decodes one argument out of `params`, taken from position `index` of an
+/// array or from the member `name` of an object. A missing argument decodes
+/// as `null`, so it is accepted for `Option` arguments only.
+pub fn param<T: Decodable>(params: &Option<Json>, index: usize, name: &str) -> Result<T, DecoderError> {
+    let value = match *params {
+        Some(Json::Array(ref values))  => values.get(index).cloned(),
+        Some(Json::Object(ref values)) => values.get(name).cloned(),
+        _                              => None
+    };
+    decode(value.unwrap_or(Json::Null))
+}
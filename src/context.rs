@@ -0,0 +1,75 @@
+
+use std::collections::BTreeMap;
+use ::{Json, TraceContext};
+use auth::{Credentials, Identity};
+
+/// Per-call state shared by the middleware chain of a `JsonRpc`.
+///
+/// Transports create one `Context` per incoming request and may fill in
+/// whatever they know about the caller (e.g. the peer address) before
+/// dispatching with `JsonRpc::call_with`.
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    peer    : Option<String>,
+    values  : BTreeMap<String, Json>,
+    warnings: Vec<String>,
+    credentials: Option<Credentials>,
+    identity: Option<Identity>,
+    trace   : Option<TraceContext>
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+    pub fn with_peer(peer: &str) -> Context {
+        Context { peer: Some(peer.to_string()), ..Context::default() }
+    }
+    pub fn peer(&self) -> Option<&str> {
+        self.peer.as_ref().map(|peer| peer.as_ref())
+    }
+    pub fn set_peer(&mut self, peer: &str) {
+        self.peer = Some(peer.to_string());
+    }
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        self.values.get(key)
+    }
+    pub fn set(&mut self, key: &str, value: Json) -> Option<Json> {
+        self.values.insert(key.to_string(), value)
+    }
+    pub fn remove(&mut self, key: &str) -> Option<Json> {
+        self.values.remove(key)
+    }
+    /// Warnings raised while handling the call, such as the use of a
+    /// deprecated method. Transports may pass them on to the caller, e.g.
+    /// in an HTTP `Warning` header.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+    pub fn warn(&mut self, warning: String) {
+        self.warnings.push(warning);
+    }
+    /// Credentials the caller presented to the transport.
+    pub fn credentials(&self) -> Option<&Credentials> {
+        self.credentials.as_ref()
+    }
+    pub fn set_credentials(&mut self, credentials: Credentials) {
+        self.credentials = Some(credentials);
+    }
+    /// Who is calling, once `Auth` or the transport established it.
+    pub fn identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+    pub fn set_identity(&mut self, identity: Identity) {
+        self.identity = Some(identity);
+    }
+    /// The trace context the transport received the call with, e.g. from
+    /// the `traceparent` HTTP header. A `traceparent` in the request itself
+    /// takes precedence.
+    pub fn trace(&self) -> Option<&TraceContext> {
+        self.trace.as_ref()
+    }
+    pub fn set_trace(&mut self, trace: TraceContext) {
+        self.trace = Some(trace);
+    }
+}
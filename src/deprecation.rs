@@ -0,0 +1,41 @@
+
+/// Deprecation notice attached to a method name with `JsonRpc::deprecate`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Deprecation {
+    message: Option<String>,
+    sunset : Option<String>
+}
+
+impl Deprecation {
+    pub fn new() -> Deprecation {
+        Deprecation::default()
+    }
+    /// Free-form hint for the caller, e.g. `"use user.get instead"`.
+    pub fn message(mut self, message: &str) -> Deprecation {
+        self.message = Some(message.to_string());
+        self
+    }
+    /// Date after which the method may be removed, e.g. `"2017-01-01"`.
+    pub fn sunset(mut self, date: &str) -> Deprecation {
+        self.sunset = Some(date.to_string());
+        self
+    }
+    pub fn get_message(&self) -> Option<&str> {
+        self.message.as_ref().map(|message| message.as_ref())
+    }
+    pub fn get_sunset(&self) -> Option<&str> {
+        self.sunset.as_ref().map(|date| date.as_ref())
+    }
+    /// The warning logged and recorded in the call `Context` when
+    /// `method` is called.
+    pub fn warning(&self, method: &str) -> String {
+        let mut warning = format!("method \"{}\" is deprecated", method);
+        if let Some(ref message) = self.message {
+            warning = warning + ": " + message;
+        }
+        if let Some(ref date) = self.sunset {
+            warning = warning + " (sunset " + date + ")";
+        }
+        warning
+    }
+}
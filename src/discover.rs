@@ -0,0 +1,313 @@
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use ::{JsonRpc, Routes, Change, Error, Json, ToJson, into_handle};
+use schema::{validate, pointer_push, SchemaError};
+
+/// Version of the OpenRPC specification `JsonRpc::openrpc` documents follow.
+pub const OPENRPC_VERSION: &'static str = "1.2.6";
+
+/// Name of the method serving the OpenRPC document, reserved by the spec.
+pub const DISCOVER_METHOD: &'static str = "rpc.discover";
+
+/// Optional description of a method, attached with `JsonRpc::describe`.
+#[derive(Debug, Clone, Default)]
+pub struct MethodInfo {
+    summary: Option<String>,
+    params : Option<Vec<ParamInfo>>,
+    result : Option<Json>,
+    errors : Vec<(i64, String)>
+}
+
+impl MethodInfo {
+    pub fn new() -> MethodInfo {
+        MethodInfo::default()
+    }
+    pub fn summary(mut self, summary: &str) -> MethodInfo {
+        self.summary = Some(summary.to_string());
+        self
+    }
+    /// Appends a parameter; positional params are matched in this order.
+    pub fn param(mut self, param: ParamInfo) -> MethodInfo {
+        self.params.get_or_insert_with(Vec::new).push(param);
+        self
+    }
+    /// JSON Schema of the result.
+    pub fn result(mut self, schema: Json) -> MethodInfo {
+        self.result = Some(schema);
+        self
+    }
+    /// An error the method may return.
+    pub fn error(mut self, code: i64, message: &str) -> MethodInfo {
+        self.errors.push((code, message.to_string()));
+        self
+    }
+    pub fn get_summary(&self) -> Option<&str> {
+        self.summary.as_ref().map(|summary| summary.as_ref())
+    }
+    pub fn get_params(&self) -> &[ParamInfo] {
+        self.params.as_ref().map_or(&[], |params| &params[..])
+    }
+    pub fn get_result(&self) -> Option<&Json> {
+        self.result.as_ref()
+    }
+    pub fn get_errors(&self) -> &[(i64, String)] {
+        &self.errors
+    }
+    /// Checks `params` against the declared parameters.
+    ///
+    /// Positional params are matched by index and named ones by name;
+    /// params that were not declared are reported, as are missing required
+    /// ones. Pointers are relative to the `params` member.
+    ///
+    /// A description that never declared parameters, e.g. one only giving
+    /// a summary, accepts any params; one declared with an empty list
+    /// accepts none.
+    pub fn validate_params(&self, params: &Option<Json>) -> Vec<SchemaError> {
+        let mut errors = Vec::new();
+        let declared = match self.params {
+            Some(ref declared) => declared,
+            None               => return errors
+        };
+        match *params {
+            Some(Json::Array(ref values)) => {
+                for (i, param) in declared.iter().enumerate() {
+                    let pointer = pointer_push("", &i.to_string());
+                    match values.get(i) {
+                        Some(value) => errors.extend(prefixed(&pointer, validate(&param.schema, value))),
+                        None if param.required => errors.push(SchemaError::new(&pointer, "is required")),
+                        None => {}
+                    }
+                }
+                for i in declared.len() .. values.len() {
+                    errors.push(SchemaError::new(&pointer_push("", &i.to_string()), "is not allowed"));
+                }
+            },
+            Some(Json::Object(ref values)) => {
+                for param in declared.iter() {
+                    let pointer = pointer_push("", &param.name);
+                    match values.get(&param.name) {
+                        Some(value) => errors.extend(prefixed(&pointer, validate(&param.schema, value))),
+                        None if param.required => errors.push(SchemaError::new(&pointer, "is required")),
+                        None => {}
+                    }
+                }
+                for name in values.keys().filter(|name| !declared.iter().any(|p| &p.name == *name)) {
+                    errors.push(SchemaError::new(&pointer_push("", name), "is not allowed"));
+                }
+            },
+            _ => {
+                for param in declared.iter().filter(|param| param.required) {
+                    errors.push(SchemaError::new(&pointer_push("", &param.name), "is required"));
+                }
+            }
+        }
+        errors
+    }
+    /// Maps `params`, given by position or by name, to an array holding one
+    /// value per declared parameter, in declaration order. Omitted params
+    /// take their default value, or `null` if they have none.
+    pub fn map_params(&self, params: &Option<Json>) -> Json {
+        let values = self.get_params().iter().enumerate().map(|(i, param)| {
+            let value = match *params {
+                Some(Json::Array(ref values))  => values.get(i),
+                Some(Json::Object(ref values)) => values.get(&param.name),
+                _                              => None
+            };
+            value.or(param.default.as_ref()).cloned().unwrap_or(Json::Null)
+        }).collect::<Vec<Json>>();
+        Json::Array(values)
+    }
+    /// Checks `result` against the declared result schema, if any.
+    pub fn validate_result(&self, result: &Json) -> Vec<SchemaError> {
+        match self.result {
+            Some(ref schema) => validate(schema, result),
+            None             => Vec::new()
+        }
+    }
+}
+
+fn prefixed(pointer: &str, errors: Vec<SchemaError>) -> Vec<SchemaError> {
+    errors.into_iter()
+          .map(|error| SchemaError::new(&(pointer.to_string() + error.pointer()), error.message()))
+          .collect()
+}
+
+/// A named method parameter with its JSON Schema.
+#[derive(Debug, Clone)]
+pub struct ParamInfo {
+    name    : String,
+    schema  : Json,
+    required: bool,
+    default : Option<Json>
+}
+
+impl ParamInfo {
+    /// A required parameter.
+    pub fn new(name: &str, schema: Json) -> ParamInfo {
+        ParamInfo { name: name.to_string(), schema: schema, required: true, default: None }
+    }
+    /// A required parameter accepting any value.
+    pub fn named(name: &str) -> ParamInfo {
+        ParamInfo::new(name, Json::Object(BTreeMap::new()))
+    }
+    pub fn optional(mut self) -> ParamInfo {
+        self.required = false;
+        self
+    }
+    /// Makes the parameter optional, taking `value` when it is omitted.
+    pub fn default(mut self, value: Json) -> ParamInfo {
+        self.required = false;
+        self.default  = Some(value);
+        self
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn schema(&self) -> &Json {
+        &self.schema
+    }
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+    pub fn get_default(&self) -> Option<&Json> {
+        self.default.as_ref()
+    }
+    // The schema with the default, if any, under `default`, as OpenRPC
+    // documents it. Boolean schemas cannot hold it: `true` becomes an empty
+    // schema, and `false` accepts nothing, so has no default worth showing.
+    fn schema_document(&self) -> Json {
+        match (self.schema.clone(), self.default.clone()) {
+            (Json::Object(mut schema), Some(default)) => {
+                schema.insert("default".to_string(), default);
+                Json::Object(schema)
+            },
+            (Json::Boolean(true), Some(default)) => {
+                let mut schema = BTreeMap::new();
+                schema.insert("default".to_string(), default);
+                Json::Object(schema)
+            },
+            (schema, _) => schema
+        }
+    }
+}
+
+impl ToJson for ParamInfo {
+    fn to_json(&self) -> Json {
+        let mut json = BTreeMap::new();
+        json.insert("name".to_string(),     self.name.to_json()     );
+        json.insert("schema".to_string(),   self.schema_document()  );
+        json.insert("required".to_string(), self.required.to_json() );
+        Json::Object(json)
+    }
+}
+
+impl JsonRpc {
+    /// Attaches a description to `method`, used by `rpc.discover`.
+    pub fn describe(&self, method: &str, info: MethodInfo) {
+        self.routes.write().unwrap().infos.insert(method.to_string(), info);
+    }
+    /// Registers `handle` with declared parameters.
+    ///
+    /// Callers may pass params by position or by name: either way the
+    /// handler receives an array with one value per parameter, in the order
+    /// given here, with defaults filled in. Unknown and missing required
+    /// params are rejected with `InvalidParams` before the handler runs.
+    ///
+    /// The parameters become part of the method's description; a later
+    /// `describe` replaces them.
+    pub fn register_params<F, E>(&self, method: &str, params: Vec<ParamInfo>, handle: Box<F>)
+        where F: Fn(&Option<Json>) -> Result<Json, E> + Send + Sync + ?Sized + 'static, E: Into<Error> {
+        let previous = {
+            // Calls never see the handler without its params.
+            let mut routes = self.routes.write().unwrap();
+            routes.infos.entry(method.to_string()).or_insert_with(MethodInfo::default).params = Some(params);
+            routes.mapped.insert(method.to_string());
+            routes.methods.insert(method.to_string(), Arc::new(into_handle(handle)))
+        };
+        match previous {
+            Some(_) => self.notify(Change::Replaced(method.to_string())),
+            None    => self.notify(Change::Registered(method.to_string()))
+        }
+    }
+    pub fn descriptions(&self) -> BTreeMap<String, MethodInfo> {
+        self.routes.read().unwrap().infos.clone()
+    }
+    /// Sets the `info.title` and `info.version` of the OpenRPC document.
+    pub fn set_info(&mut self, title: &str, version: &str) {
+        self.info = (title.to_string(), version.to_string());
+    }
+    /// Builds an OpenRPC document listing every registered method and
+    /// alias, with whatever was declared for them through `describe`, and
+    /// the error codes declared with `register_errors`.
+    pub fn openrpc(&self) -> Json {
+        let mut info = BTreeMap::new();
+        info.insert("title".to_string(),   self.info.0.to_json() );
+        info.insert("version".to_string(), self.info.1.to_json() );
+
+        let routes = self.routes.read().unwrap();
+        let methods = routes.methods.keys()
+                                    .chain(routes.aliases.keys())
+                                    .map(|name| method_document(&routes, name))
+                                    .collect::<Vec<Json>>();
+        let mut json = BTreeMap::new();
+        json.insert("openrpc".to_string(), OPENRPC_VERSION.to_json() );
+        json.insert("info".to_string(),    Json::Object(info)        );
+        json.insert("methods".to_string(), Json::Array(methods)      );
+        if !self.errors.is_empty() {
+            let errors = self.errors.iter().map(|(code, message)| {
+                (code.to_string(), error_document(*code, message))
+            }).collect::<BTreeMap<String, Json>>();
+            let mut components = BTreeMap::new();
+            components.insert("errors".to_string(), Json::Object(errors));
+            json.insert("components".to_string(), Json::Object(components));
+        }
+        Json::Object(json)
+    }
+    /// The description of `method`. Aliases and fallbacks share the
+    /// description of the method serving them unless they have their own.
+    pub fn description(&self, method: &str) -> Option<MethodInfo> {
+        self.routes.read().unwrap().description(method).cloned()
+    }
+    /// Enables checking results against the declared result schemas. A
+    /// result that does not match is replaced by an `InternalError`; this is
+    /// meant for development and tests.
+    pub fn validate_results(&mut self, enabled: bool) {
+        self.validate_results = enabled;
+    }
+}
+
+fn method_document(routes: &Routes, name: &str) -> Json {
+    let info = routes.description(name);
+    let default = MethodInfo::default();
+    let info = info.unwrap_or(&default);
+
+    let mut result = BTreeMap::new();
+    result.insert("name".to_string(),   "result".to_json() );
+    result.insert("schema".to_string(), info.result.clone().unwrap_or(Json::Object(BTreeMap::new())) );
+    let errors = info.errors.iter()
+                            .map(|&(code, ref message)| error_document(code, message))
+                            .collect::<Vec<Json>>();
+
+    let mut json = BTreeMap::new();
+    json.insert("name".to_string(),   name.to_json() );
+    json.insert("params".to_string(), info.get_params().to_json() );
+    json.insert("result".to_string(), Json::Object(result) );
+    if let Some(ref summary) = info.summary {
+        json.insert("summary".to_string(), summary.to_json() );
+    }
+    if !errors.is_empty() {
+        json.insert("errors".to_string(), Json::Array(errors) );
+    }
+    if routes.deprecated.contains_key(name) {
+        json.insert("deprecated".to_string(), true.to_json() );
+    }
+    Json::Object(json)
+}
+
+fn error_document(code: i64, message: &str) -> Json {
+    let mut error = BTreeMap::new();
+    error.insert("code".to_string(),    code.to_json()    );
+    error.insert("message".to_string(), message.to_json() );
+    Json::Object(error)
+}
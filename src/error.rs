@@ -1,17 +1,19 @@
 
+use std::backtrace::{Backtrace, BacktraceStatus};
 use std::collections::BTreeMap;
-// use std::str::FromStr;
-use std::string::ToString;
+use std::error;
+use std::fmt;
+use rustc_serialize::json::{ParserError, DecoderError, EncoderError};
 use ::{Json, ToJson, Object};
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Error {
     ParseError,                             // -32700
     InvalidRequest,                         // -32600
     MethodNotFound,                         // -32601
-    InvalidParams,                          // -32602
-    InternalError,                          // -32603
+    InvalidParams( Option<Json> ),          // -32602
+    InternalError( Option<Json> ),          // -32603
     ServerError( i64,  String,  Option<Json>), // -32000 to -32099
     Unregister ( i64,  String,  Option<Json>)  // other code.
 }
@@ -29,20 +31,103 @@ impl ToJson for Error {
     }
 }
 
-impl ToString for Error {
-    fn to_string(&self) -> String {
-        self.to_json().to_string()
+/// The message and code, e.g. `Method not found (-32601)`; use `to_json`
+/// for the error object sent over the wire.
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.to_message(), self.to_i64())
+    }
+}
+
+impl error::Error for Error {}
+
+/// Malformed JSON text.
+impl From<ParserError> for Error {
+    fn from(_: ParserError) -> Error {
+        Error::ParseError
+    }
+}
+
+/// Malformed JSON text is a `ParseError`. Otherwise the params do not
+/// decode into the expected type, and the reason goes into the `data` of
+/// an `InvalidParams`.
+impl From<DecoderError> for Error {
+    fn from(err: DecoderError) -> Error {
+        match err {
+            DecoderError::ParseError(_) => Error::ParseError,
+            err                         => Error::InvalidParams(Some(Json::String(err.to_string())))
+        }
+    }
+}
+
+impl From<EncoderError> for Error {
+    fn from(err: EncoderError) -> Error {
+        Error::InternalError(Some(Json::String(err.to_string())))
+    }
+}
+
+/// A generic server error (-32000) with the given message.
+impl<'a> From<&'a str> for Error {
+    fn from(message: &'a str) -> Error {
+        Error::server(-32000, message)
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::ServerError(-32000, message, None)
     }
 }
 
 impl Error {
+    /// The error with `code`. Predefined errors keep their standard
+    /// message; any other code takes `message`.
+    pub fn new(code: i64, message: &str, data: Option<Json>) -> Error {
+        let mut error = Error::from_i64(code).unwrap();
+        error.set_message(message.to_string());
+        error.set_data(data);
+        error
+    }
+    pub fn invalid_params(data: Json) -> Error {
+        Error::InvalidParams(Some(data))
+    }
+    pub fn internal(data: Json) -> Error {
+        Error::InternalError(Some(data))
+    }
+    /// An `InternalError` describing `err`: its message and those of its
+    /// sources go into `causes`, and a backtrace of the caller into
+    /// `backtrace` when `RUST_BACKTRACE` enables them. How much of this
+    /// reaches the client is up to `JsonRpc::set_exposure`.
+    pub fn from_cause(err: &dyn error::Error) -> Error {
+        let mut causes = vec![err.to_string().to_json()];
+        let mut source = err.source();
+        while let Some(err) = source {
+            causes.push(err.to_string().to_json());
+            source = err.source();
+        }
+        let mut data = BTreeMap::new();
+        data.insert("causes".to_string(), Json::Array(causes));
+        let backtrace = Backtrace::capture();
+        if backtrace.status() == BacktraceStatus::Captured {
+            data.insert("backtrace".to_string(), backtrace.to_string().to_json());
+        }
+        Error::InternalError(Some(Json::Object(data)))
+    }
+    /// A `ServerError`, or an `Unregister` error when `code` is outside of
+    /// the range reserved for server errors (-32099 to -32000).
+    pub fn server(code: i64, message: &str) -> Error {
+        match code {
+            -32099 ..= -32000 => Error::ServerError(code, message.to_string(), None),
+            _                 => Error::Unregister(code, message.to_string(), None)
+        }
+    }
     pub fn from_i64(n: i64) -> Result< Error, &'static str> {
         match n {
             -32700i64 => Ok(Error::ParseError),
             -32600i64 => Ok(Error::InvalidRequest),
             -32601i64 => Ok(Error::MethodNotFound),
-            -32602i64 => Ok(Error::InvalidParams),
-            -32603i64 => Ok(Error::InternalError),
+            -32602i64 => Ok(Error::InvalidParams(None)),
+            -32603i64 => Ok(Error::InternalError(None)),
             code@ -32099 ... -32000 => Ok(Error::ServerError(code, "".to_string(), None)),
             code@ _                 => Ok(Error::Unregister(code, "".to_string(), None))
         }
@@ -52,8 +137,8 @@ impl Error {
             Error::ParseError     => -32700i64,
             Error::InvalidRequest => -32600i64,
             Error::MethodNotFound => -32601i64,
-            Error::InvalidParams  => -32602i64,
-            Error::InternalError  => -32603i64,
+            Error::InvalidParams(_) => -32602i64,
+            Error::InternalError(_) => -32603i64,
             Error::ServerError(ref code, _, _) => code.clone(),
             Error::Unregister (ref code, _, _) => code.clone()
         }
@@ -63,8 +148,8 @@ impl Error {
             Error::ParseError     => "Parse error".to_string(),
             Error::InvalidRequest => "Invalid Request".to_string(),
             Error::MethodNotFound => "Method not found".to_string(),
-            Error::InvalidParams  => "Invalid method parameter(s)".to_string(),
-            Error::InternalError  => "Internal error".to_string(),
+            Error::InvalidParams(_) => "Invalid method parameter(s)".to_string(),
+            Error::InternalError(_) => "Internal error".to_string(),
             Error::ServerError(_, ref message, _) => message.clone(),
             Error::Unregister (_, ref message, _) => message.clone(),
         }
@@ -74,8 +159,8 @@ impl Error {
             Error::ParseError     => None,
             Error::InvalidRequest => None,
             Error::MethodNotFound => None,
-            Error::InvalidParams  => None,
-            Error::InternalError  => None,
+            Error::InvalidParams(ref data) => data.clone(),
+            Error::InternalError(ref data) => data.clone(),
             Error::ServerError(_, _, ref data) => data.clone(),
             Error::Unregister (_, _, ref data) => data.clone(),
         }
@@ -95,6 +180,14 @@ impl Error {
     }
     pub fn set_data(&mut self, _data: Option<Json>) -> bool {
         match self {
+            &mut Error::InvalidParams(ref mut data) => {
+                *data = _data;
+                true
+            },
+            &mut Error::InternalError(ref mut data) => {
+                *data = _data;
+                true
+            },
             &mut Error::ServerError(_, _, ref mut data) => {
                 *data = _data;
                 true
@@ -106,54 +199,33 @@ impl Error {
             _ => false
         }
     }
-    pub fn _parse_error(obj: &Object) -> Result<(i64, String, Option<Json>), ()> {
-        let result = match obj.get("error") {
-            Some(result) => result,
-            None         => return Err(())
+    /// Reads the `error` member of a response. Fails with the reason when
+    /// it is missing or is not an object with an integer `code`, a string
+    /// `message` and optional `data`.
+    pub fn _parse_error(obj: &Object) -> Result<(i64, String, Option<Json>), &'static str> {
+        let error = match obj.get("error") {
+            Some(&Json::Object(ref error)) => error,
+            Some(_) => return Err("\"error\" is not an object"),
+            None    => return Err("missing \"error\"")
         };
-        if !result.is_object() {
-            return Err(());
-        }
-        match result.as_object() {
-            Some(ref result) => {
-                let code    = match result.get("code"){
-                    Some(code) => {
-                        if code.is_i64() {
-                            code.as_i64().unwrap()
-                        } else if code.is_u64() {
-                            code.as_u64().unwrap() as i64
-                        } else if code.is_f64() {
-                            code.as_f64().unwrap() as i64
-                        } else {
-                            return Err(());
-                        }
-                    },
-                    None => return Err(())
-                };
-                let message = match result.get("message"){
-                    Some(message) => message.as_string().unwrap().to_string(),
-                    None          => String::new()
-                };
-                let data    = match result.get("data"){
-                    Some(data) => match data.is_null(){
-                        true  => None,
-                        false => Some(data.clone())
-                    },
-                    None       => None
-                };
-                // let error = match Error::from_i64(code){
-                //     Ok(ref mut e) => {
-                //         e.set_message(message);
-                //         e.set_data(data);
-                //         e
-                //     },
-                //     Err(_) => return Err(())
-                // };
-                // Ok(*&*error)
-                Ok((code, message, data))
-
-            },
-            None => Err(())
-        }
+        let code = match error.get("code") {
+            Some(&Json::I64(code)) => code,
+            Some(&Json::U64(code)) if code <= i64::max_value() as u64 => code as i64,
+            // Some encoders write integral codes as `-32601.0`; anything
+            // else would lose precision.
+            Some(&Json::F64(code)) if code.fract() == 0.0 && code.abs() < 9007199254740992.0 => code as i64,
+            Some(_) => return Err("\"code\" is not an integer"),
+            None    => return Err("missing \"code\" in \"error\"")
+        };
+        let message = match error.get("message") {
+            Some(&Json::String(ref message)) => message.clone(),
+            Some(_) => return Err("\"message\" is not a string"),
+            None    => return Err("missing \"message\" in \"error\"")
+        };
+        let data = match error.get("data") {
+            Some(&Json::Null) | None => None,
+            Some(data)               => Some(data.clone())
+        };
+        Ok((code, message, data))
     }
 }
@@ -0,0 +1,94 @@
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use getrandom::getrandom;
+use rustc_serialize::hex::ToHex;
+use ::{JsonRpc, Error, Json, ToJson};
+
+/// How much detail about internal failures `JsonRpc` sends to clients.
+///
+/// Internal failures are `InternalError` and `ServerError` responses to a
+/// call. Each one is logged in full with a correlation id, which the client
+/// receives as the `correlation_id` member of `data`. Protocol errors such
+/// as `InvalidParams` and application errors are meant for the client and
+/// always sent as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exposure {
+    /// Errors are sent as the handler returned them, with the correlation id
+    /// added to their `data`; data that is not an object moves to its
+    /// `data` member.
+    Standard,
+    /// Only a generic message and a correlation id are sent; the error
+    /// itself is logged with the same id. Meant for production.
+    Minimal,
+    /// Besides the correlation id, `data` holds the method's handler and the
+    /// error's own data, e.g. the causes and backtrace recorded by
+    /// `Error::from_cause`. Meant for development.
+    Full
+}
+
+impl Default for Exposure {
+    fn default() -> Exposure {
+        Exposure::Standard
+    }
+}
+
+impl JsonRpc {
+    /// Sets how much detail internal failures expose; `Exposure::Standard`
+    /// by default.
+    pub fn set_exposure(&mut self, exposure: Exposure) {
+        self.exposure = exposure;
+    }
+    pub fn exposure(&self) -> Exposure {
+        self.exposure
+    }
+    /// Applies the exposure policy to `error`, returned for `method` whose
+    /// handler is registered as `handler`.
+    pub(crate) fn expose(&self, method: &str, handler: &str, error: Error) -> Error {
+        match error {
+            Error::InternalError(_) | Error::ServerError(..) => {},
+            _ => return error
+        }
+        let id = correlation_id();
+        error!("{} failed [{}]: {} {}", method, id, error, error.to_data().unwrap_or(Json::Null));
+
+        let mut data = BTreeMap::new();
+        data.insert("correlation_id".to_string(), id.to_json());
+        match self.exposure {
+            Exposure::Standard => {
+                match error.to_data() {
+                    Some(Json::Object(object)) => data.extend(object.into_iter().filter(|&(ref key, _)| key != "correlation_id")),
+                    Some(other)                => { data.insert("data".to_string(), other); },
+                    None                       => {}
+                }
+                let mut error = error;
+                error.set_data(Some(Json::Object(data)));
+                error
+            },
+            Exposure::Full => {
+                data.insert("handler".to_string(), handler.to_json());
+                data.insert("message".to_string(), error.to_message().to_json());
+                data.insert("data".to_string(),    error.to_data().unwrap_or(Json::Null));
+                let mut error = error;
+                error.set_data(Some(Json::Object(data)));
+                error
+            },
+            _ => match error {
+                Error::ServerError(code, _, _) => Error::ServerError(code, "Server error".to_string(), Some(Json::Object(data))),
+                _                              => Error::InternalError(Some(Json::Object(data)))
+            }
+        }
+    }
+}
+
+/// An id to find a failure in the logs: 64 random bits, which tell
+/// processes apart, followed by a counter, so that no two failures of the
+/// same process share an id.
+///
+/// Panics if the operating system has no randomness to give.
+fn correlation_id() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let mut bytes = [0u8; 8];
+    getrandom(&mut bytes).expect("no randomness for correlation ids");
+    format!("{}{:016x}", bytes.to_hex(), COUNTER.fetch_add(1, Ordering::SeqCst))
+}
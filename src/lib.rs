@@ -1,9 +1,22 @@
 #[warn(non_shorthand_field_patterns)]
 
 extern crate rustc_serialize;
+#[macro_use]
+extern crate log;
+extern crate jsonrpc2_derive;
+extern crate getrandom;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 
-use std::collections::BTreeMap;
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet};
+use std::panic::{self, AssertUnwindSafe};
 use std::str::FromStr;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{Instant, SystemTime};
 use std::string::ToString;
 pub use rustc_serialize::json::{Json, ToJson, Object};
 
@@ -11,37 +24,498 @@ mod error;
 mod request;
 mod response;
 mod client;
+mod context;
+mod middleware;
+mod deprecation;
+mod discover;
+mod schema;
+mod protocol;
+mod app_error;
+mod exposure;
+mod local;
+mod registry;
+mod limit;
+mod auth;
+mod server;
+mod trace;
+mod access_log;
+mod record;
+mod mock;
+#[cfg(feature = "metrics")]
+mod metrics;
+pub mod codec;
 
 pub use error::Error;
 pub use request::Request;
-pub use response::Response;
-pub use client::Client;
+pub use response::{Response, ResponseError};
+pub use client::{Client, ClientError, Transport};
+pub use context::Context;
+pub use middleware::{Middleware, Layer, Next};
+pub use deprecation::Deprecation;
+pub use discover::{MethodInfo, ParamInfo, DISCOVER_METHOD, OPENRPC_VERSION};
+pub use schema::{validate, SchemaError};
+pub use protocol::{Mode, Deviation, Version, Id};
+pub use app_error::{AppError, is_reserved};
+pub use exposure::Exposure;
+pub use local::{LocalJsonRpc, LocalHandle};
+pub use registry::Change;
+pub use limit::{ConcurrencyLimit, RateLimit, LIMIT_ERROR_CODE};
+pub use auth::{Auth, Authenticator, Credentials, Identity, Requirement, Sessions, UNAUTHENTICATED_CODE, FORBIDDEN_CODE};
+pub use server::HttpHandler;
+pub use trace::{TraceContext, TRACEPARENT};
+pub use access_log::{AccessLog, LogFormat, REDACTED};
+pub use record::{Recorder, Exchange, Replay, Mismatch, Side};
+pub use mock::{Mock, Expectation, Params};
+#[cfg(feature = "metrics")]
+pub use metrics::{Metrics, UNKNOWN_METHOD};
+pub use jsonrpc2_derive::{rpc, AppError};
 
 pub type RpcResult = Result<Json, &'static str>;
-pub type RpcHandle = Box<Fn(&Option<Json>)-> RpcResult>;
+/// A method handler. Handlers are shared by every thread serving the
+/// `JsonRpc`; see `LocalJsonRpc` for handlers that are not thread-safe.
+pub type RpcHandle = Box<Fn(&Option<Json>)-> Result<Json, Error> + Send + Sync>;
 
 
+fn into_handle<F, E>(handle: Box<F>) -> RpcHandle
+    where F: Fn(&Option<Json>) -> Result<Json, E> + Send + Sync + ?Sized + 'static, E: Into<Error> {
+    Box::new(move |params: &Option<Json>| handle(params).map_err(Into::into))
+}
+
+/// Everything deciding which handler serves a method and how it is
+/// called, changed together under one lock while the dispatcher serves.
+#[derive(Default)]
+struct Routes {
+    methods   : BTreeMap<String, Arc<RpcHandle>>,
+    aliases   : BTreeMap<String, String>,
+    deprecated: BTreeMap<String, Deprecation>,
+    fallbacks : Vec<(String, String)>,
+    infos     : BTreeMap<String, MethodInfo>,
+    mapped    : BTreeSet<String>
+}
+
+impl Routes {
+    /// The names a call of `method` goes through and the handler it ends
+    /// at; see `JsonRpc::route`.
+    fn route(&self, method: &str) -> (Vec<String>, Option<Arc<RpcHandle>>) {
+        let mut names = vec![method.to_string()];
+        // Each step follows one alias or fallback; more steps than there
+        // are of those means the routes form a cycle.
+        for _ in 0 .. self.aliases.len() + self.fallbacks.len() + 1 {
+            let name = names[names.len() - 1].clone();
+            if let Some(handle) = self.methods.get(&name) {
+                return (names, Some(handle.clone()));
+            }
+            if let Some(target) = self.aliases.get(&name) {
+                names.push(target.clone());
+                continue;
+            }
+            let fallback = self.fallbacks.iter().find(|&&(ref from, _)| name.starts_with(from.as_str()));
+            match fallback {
+                Some(&(ref from, ref to)) => names.push(to.clone() + &name[from.len() ..]),
+                None                      => return (names, None)
+            }
+        }
+        (names, None)
+    }
+    fn resolve(&self, method: &str) -> Option<(String, Arc<RpcHandle>)> {
+        match self.route(method) {
+            (mut names, Some(handle)) => names.pop().map(|name| (name, handle)),
+            (_, None)                 => None
+        }
+    }
+    /// The description of `method`, or else of the method serving it.
+    fn description(&self, method: &str) -> Option<&MethodInfo> {
+        match self.infos.get(method) {
+            Some(info) => Some(info),
+            None       => self.resolve(method).and_then(|(target, _)| self.infos.get(&target))
+        }
+    }
+}
+
+/// The registered methods of a `JsonRpc`, locked against changes while
+/// this guard is held.
+pub struct Methods<'a>(RwLockReadGuard<'a, Routes>);
+
+impl<'a> Deref for Methods<'a> {
+    type Target = BTreeMap<String, Arc<RpcHandle>>;
+    fn deref(&self) -> &BTreeMap<String, Arc<RpcHandle>> {
+        &self.0.methods
+    }
+}
+
 pub struct JsonRpc {
-    methods : BTreeMap<String, RpcHandle>,
+    routes    : RwLock<Routes>,
+    watchers  : Mutex<Vec<Sender<Change>>>,
+    layers    : Vec<Layer>,
+    info      : (String, String),
+    validate_results: bool,
+    mode      : Mode,
+    version   : Version,
+    errors    : BTreeMap<i64, String>,
+    exposure  : Exposure,
+    catch_panics: bool,
+    panics    : AtomicUsize,
+    #[cfg(feature = "metrics")]
+    metrics   : Arc<Metrics>,
+    recorder  : Option<Arc<Recorder>>,
     // TODO: Add Shared Memory.
 }
 
 impl JsonRpc {
     pub fn new () -> JsonRpc {
-        JsonRpc { methods : BTreeMap::new() }
+        JsonRpc {
+            routes    : RwLock::new(Routes::default()),
+            watchers  : Mutex::new(Vec::new()),
+            layers    : Vec::new(),
+            info      : ("JSON-RPC".to_string(), "0.0.0".to_string()),
+            validate_results: false,
+            mode      : Mode::Lenient,
+            version   : Version::default(),
+            errors    : BTreeMap::new(),
+            exposure  : Exposure::default(),
+            catch_panics: false,
+            panics    : AtomicUsize::new(0),
+            #[cfg(feature = "metrics")]
+            metrics   : Arc::new(Metrics::new()),
+            recorder  : None
+        }
+    }
+    /// Registers `handle` under `method`, replacing any handler registered
+    /// under that name. Handlers may fail with any error that converts into
+    /// an `Error`: a `&str` or `String` becomes a `ServerError` (-32000) and
+    /// an `AppError` keeps its own code.
+    ///
+    /// Methods may be registered while the dispatcher is serving calls, as
+    /// may aliases, fallbacks, deprecations and descriptions; see
+    /// `unregister`, `replace` and `watch`.
+    pub fn register<F, E>(&self, method: &str, handle: Box<F>)
+        where F: Fn(&Option<Json>) -> Result<Json, E> + Send + Sync + ?Sized + 'static, E: Into<Error> {
+        let previous = self.routes.write().unwrap().methods.insert(method.to_string(), Arc::new(into_handle(handle)));
+        match previous {
+            Some(_) => self.notify(Change::Replaced(method.to_string())),
+            None    => self.notify(Change::Registered(method.to_string()))
+        }
+    }
+    /// The registered methods, locked against changes while the guard is
+    /// held.
+    pub fn methods(&self) -> Methods<'_> {
+        Methods(self.routes.read().unwrap())
+    }
+    /// Makes `alias` call the same handler as `method`.
+    pub fn alias(&self, alias: &str, method: &str) {
+        self.routes.write().unwrap().aliases.insert(alias.to_string(), method.to_string());
+    }
+    pub fn aliases(&self) -> BTreeMap<String, String> {
+        self.routes.read().unwrap().aliases.clone()
+    }
+    /// Marks `method` (a registered name or an alias) as deprecated.
+    ///
+    /// Calls still succeed, but each one logs a warning and records it in
+    /// the call `Context`, also when `method` is only passed through on
+    /// the way from another alias or fallback.
+    pub fn deprecate(&self, method: &str, deprecation: Deprecation) {
+        self.routes.write().unwrap().deprecated.insert(method.to_string(), deprecation);
+    }
+    pub fn deprecations(&self) -> BTreeMap<String, Deprecation> {
+        self.routes.read().unwrap().deprecated.clone()
+    }
+    /// Routes methods starting with `from` that have no handler of their
+    /// own to the same method under `to`.
+    ///
+    /// With `fallback("v2.", "v1.")`, `v2.add` is served by `v1.add` until
+    /// a `v2.add` is registered. Fallbacks chain, so `v3.` can fall back to
+    /// `v2.` and on to `v1.`.
+    pub fn fallback(&self, from: &str, to: &str) {
+        self.routes.write().unwrap().fallbacks.push((from.to_string(), to.to_string()));
+    }
+    /// Finds the handler serving `method`, following aliases and version
+    /// fallbacks, and returns it with the name it is registered under.
+    pub fn resolve(&self, method: &str) -> Option<(String, Arc<RpcHandle>)> {
+        self.routes.read().unwrap().resolve(method)
+    }
+    /// The names a call of `method` goes through: `method` itself, then
+    /// each alias or fallback followed from it, ending with the method
+    /// serving it if there is one.
+    pub fn route(&self, method: &str) -> Vec<String> {
+        self.routes.read().unwrap().route(method).0
+    }
+    /// Adds a middleware that runs around every call.
+    ///
+    /// Layers run in the order they were added: the first one added is the
+    /// outermost and sees the request first and the response last.
+    pub fn wrap(&mut self, middleware: Box<dyn Middleware>) {
+        self.layers.push(Layer::new(None, middleware));
+    }
+    /// Adds a middleware that only runs for methods starting with `prefix`,
+    /// including calls that reach such a method through an alias or a
+    /// fallback outside the prefix.
+    pub fn wrap_prefix(&mut self, prefix: &str, middleware: Box<dyn Middleware>) {
+        self.layers.push(Layer::new(Some(prefix), middleware));
+    }
+    /// Mounts every method of `router` under `namespace`, joined with
+    /// `separator` (e.g. `user` and `.` turn `get` into `user.get`).
+    ///
+    /// Middleware of `router` comes along, scoped to the namespace, and runs
+    /// inside the layers already added to `self`. Nothing is mounted if any
+    /// of the resulting names is already registered, or if `router`
+    /// declares an error code `self` declares with another message; the
+    /// colliding names and codes (as `error <code>`) are returned instead.
+    pub fn mount(&mut self, namespace: &str, separator: &str, router: JsonRpc) -> Result<(), Vec<String>> {
+        let prefix = namespace.to_string() + separator;
+        let JsonRpc { routes: mounted, layers, errors, .. } = router;
+        let mounted = mounted.into_inner().unwrap();
+        let mut routes = self.routes.write().unwrap();
+        let collisions = mounted.methods.keys()
+                                        .chain(mounted.aliases.keys())
+                                        .map(|method| prefix.clone() + method)
+                                        .filter(|method| routes.methods.contains_key(method)
+                                                      || routes.aliases.contains_key(method))
+                                        .chain(self.conflicting_errors(&errors).into_iter()
+                                                   .map(|code| format!("error {}", code)))
+                                        .collect::<Vec<String>>();
+        if !collisions.is_empty() {
+            return Err(collisions);
+        }
+        let Routes { methods, aliases, deprecated, fallbacks, infos, mapped } = mounted;
+        let mut registered = Vec::new();
+        for (method, handle) in methods {
+            let method = prefix.clone() + &method;
+            routes.methods.insert(method.clone(), handle);
+            registered.push(method);
+        }
+        for (alias, method) in aliases {
+            routes.aliases.insert(prefix.clone() + &alias, prefix.clone() + &method);
+        }
+        for (method, deprecation) in deprecated {
+            routes.deprecated.insert(prefix.clone() + &method, deprecation);
+        }
+        for (from, to) in fallbacks {
+            routes.fallbacks.push((prefix.clone() + &from, prefix.clone() + &to));
+        }
+        for (method, info) in infos {
+            routes.infos.insert(prefix.clone() + &method, info);
+        }
+        for method in mapped {
+            routes.mapped.insert(prefix.clone() + &method);
+        }
+        drop(routes);
+        for method in registered {
+            self.notify(Change::Registered(method));
+        }
+        for layer in layers {
+            self.layers.push(layer.nest(&prefix));
+        }
+        self.errors.extend(errors);
+        Ok(())
+    }
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+    /// Sets how strictly `handle` parses requests; `Mode::Lenient` by
+    /// default, logging each deviation from the spec it accepts. Use
+    /// `Mode::Strict` to reject them instead.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+    /// Sets the protocol version `handle` expects requests in;
+    /// `Version::V2` by default. Responses follow the version of the
+    /// request they answer.
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
     }
-    pub fn register (&mut self, method: &str, handle: RpcHandle) {
-        self.methods.insert(method.to_string(), handle);
+    /// Makes a panicking handler fail with an `InternalError` instead of
+    /// unwinding into the caller. The panic is logged and counted in
+    /// `panics`, and later calls are served as usual.
+    ///
+    /// Only handlers are covered; a panicking middleware still unwinds.
+    pub fn catch_panics(&mut self, enabled: bool) {
+        self.catch_panics = enabled;
     }
-    pub fn methods(&self) -> &BTreeMap<String, RpcHandle> {
-        &self.methods
+    /// The number of handler panics caught so far.
+    pub fn panics(&self) -> usize {
+        self.panics.load(Ordering::SeqCst)
+    }
+    fn panicked(&self, method: &str, payload: Box<dyn Any + Send>) -> Error {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => message.clone(),
+                None          => "Box<Any>".to_string()
+            }
+        };
+        self.panics.fetch_add(1, Ordering::SeqCst);
+        #[cfg(feature = "metrics")]
+        self.metrics.panic(method);
+        error!("{} panicked: {}", method, message);
+        match self.exposure {
+            Exposure::Standard => Error::InternalError(None),
+            _ => {
+                let mut data = BTreeMap::new();
+                data.insert("panic".to_string(), message.to_json());
+                Error::InternalError(Some(Json::Object(data)))
+            }
+        }
+    }
+    /// Handles a raw request body: a single request or a batch.
+    ///
+    /// Returns the body to send back, or `None` when there is nothing to
+    /// answer because the body held only notifications. Bodies that are not
+    /// JSON get a `ParseError` response and malformed requests an
+    /// `InvalidRequest` one, with the request id if it could be read.
+    pub fn handle(&self, body: &str) -> Option<String> {
+        self.handle_with(body, &mut Context::new())
+    }
+    /// Like `handle`, with a `Context` for the call. A batch shares one
+    /// context across its requests.
+    pub fn handle_with(&self, body: &str, ctx: &mut Context) -> Option<String> {
+        let (time, start) = (SystemTime::now(), Instant::now());
+        let response = self.handle_body(body, ctx);
+        if let Some(ref recorder) = self.recorder {
+            recorder.record_served(&Exchange::new(Side::Server, ctx.peer(), time, start.elapsed(), body, response.as_ref().map(|r| r.as_str())), self);
+        }
+        response
+    }
+    /// `handle_with` without recording.
+    pub(crate) fn handle_body(&self, body: &str, ctx: &mut Context) -> Option<String> {
+        let json = match Json::from_str(body) {
+            Ok(json) => json,
+            Err(_)   => return Some(Response::error(Error::ParseError, None).to_string())
+        };
+        match json {
+            Json::Array(ref batch) if batch.is_empty() => {
+                Some(Response::error(Error::InvalidRequest, None).to_string())
+            },
+            Json::Array(batch) => {
+                #[cfg(feature = "metrics")]
+                self.metrics.batch(batch.len());
+                let responses = batch.into_iter()
+                                     .filter_map(|json| self.handle_json(json, ctx))
+                                     .map(|response| response.to_json())
+                                     .collect::<Vec<Json>>();
+                match responses.is_empty() {
+                    true  => None,
+                    false => Some(Json::Array(responses).to_string())
+                }
+            },
+            json => self.handle_json(json, ctx).map(|response| response.to_string())
+        }
+    }
+    fn handle_json(&self, json: Json, ctx: &mut Context) -> Option<Response> {
+        let id = Request::parse_id(&json);
+        if self.version == Version::V1 {
+            let request = match Request::parse_v1(json) {
+                Ok(request) => request,
+                Err(error)  => return Some(Response::Error{ jsonrpc: Version::V1.as_str().to_string(),
+                                                            error  : error,
+                                                            id     : id })
+            };
+            let response = self.call_with(&request, ctx);
+            return match request.is_notification() {
+                true  => None,
+                false => Some(response)
+            };
+        }
+        let request = match Request::inspect(json, self.mode) {
+            Ok((request, deviations)) => {
+                for deviation in deviations {
+                    warn!("request {} from {}: {}", request.method(), ctx.peer().unwrap_or("unknown peer"), deviation);
+                }
+                request
+            },
+            Err(error) => return Some(Response::error(error, id))
+        };
+        let response = self.call_with(&request, ctx);
+        match request.is_notification() {
+            true  => None,
+            false => Some(response)
+        }
     }
     pub fn call(&self, request: &Request) -> Response {
-        match self.methods.get(&request.method()) {
-            Some(func) => {
+        self.call_with(request, &mut Context::new())
+    }
+    /// Runs `request` through the middleware chain and the method handler.
+    ///
+    /// Layers are selected by the names the request goes through when it
+    /// arrives, see `route`, so a middleware that renames the method does
+    /// not change which layers run after it.
+    pub fn call_with(&self, request: &Request, ctx: &mut Context) -> Response {
+        let method = request.method();
+        let route = self.route(&method);
+        let layers = self.layers.iter()
+                                .filter(|layer| route.iter().any(|name| layer.matches(name)))
+                                .collect::<Vec<&Layer>>();
+        #[cfg(feature = "metrics")]
+        let call = self.metrics.serve(self.metric_label(&method), request);
+        #[cfg(feature = "tracing")]
+        let (span, _trace) = trace::serve(request, ctx);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        let response = Next::new(self, &layers).run(request.clone(), ctx);
+        #[cfg(feature = "metrics")]
+        call.respond(&response, response.to_string().len());
+        #[cfg(feature = "tracing")]
+        trace::respond(&span, &response);
+        response
+    }
+    fn dispatch(&self, request: &Request, ctx: &mut Context) -> Response {
+        let method = request.method();
+        // Handlers may change the routes, so the lock is released before
+        // one runs.
+        let route = {
+            let routes = self.routes.read().unwrap();
+            let (names, func) = routes.route(&method);
+            func.map(|func| {
+                // Every deprecated name along the way is reported.
+                let warnings = names.iter()
+                                    .filter_map(|name| routes.deprecated.get(name).map(|deprecation| deprecation.warning(name)))
+                                    .collect::<Vec<String>>();
+                let name = names[names.len() - 1].clone();
+                let info = routes.description(&method).cloned();
+                let mapped = routes.mapped.contains(&name);
+                (name, func, warnings, info, mapped)
+            })
+        };
+        match route {
+            Some((name, func, warnings, info, mapped)) => {
+                for warning in warnings {
+                    warn!("{}", warning);
+                    ctx.warn(warning);
+                }
+                if let Some(ref info) = info {
+                    let errors = info.validate_params(&request.params());
+                    if !errors.is_empty() {
+                        return Response::Error{
+                            jsonrpc: request.jsonrpc(),
+                            error  : Error::InvalidParams(Some(errors.to_json())),
+                            id     : request.id()
+                        };
+                    }
+                }
+                let params = match info {
+                    Some(ref info) if mapped => Some(info.map_params(&request.params())),
+                    _                        => request.params()
+                };
+                let result = match self.catch_panics {
+                    false => func(&params),
+                    true  => match panic::catch_unwind(AssertUnwindSafe(|| func(&params))) {
+                        Ok(result)   => result,
+                        Err(payload) => Err(self.panicked(&method, payload))
+                    }
+                };
                 // TODO: Support Shared Memory.
-                match func(&request.params()) {
+                match result {
                     Ok(result) => {
+                        if let (true, Some(info)) = (self.validate_results, info) {
+                            let errors = info.validate_result(&result);
+                            if !errors.is_empty() {
+                                error!("result of {} does not match its schema: {}", method, errors.to_json());
+                                return Response::Error{
+                                    jsonrpc: request.jsonrpc(),
+                                    error  : self.expose(&method, &name, Error::InternalError(Some(errors.to_json()))),
+                                    id     : request.id()
+                                };
+                            }
+                        }
                         Response::Success{
                             jsonrpc: request.jsonrpc().clone(),
                             result : Some(result),
@@ -49,15 +523,26 @@ impl JsonRpc {
                         }
                     },
                     Err(err) => {
+                        if let Error::Unregister(code, _, _) = err {
+                            if !self.errors.is_empty() && !self.errors.contains_key(&code) {
+                                warn!("{} returned error code {}, which was not registered", method, code);
+                            }
+                        }
                         Response::Error{
                             jsonrpc: request.jsonrpc().clone(),
-                            // Error::InternalError
-                            error  : Error::ServerError(-32000, err.to_string(), None),
+                            error  : self.expose(&method, &name, err),
                             id     : request.id().clone()
                         }
                     }
                 }
             },
+            // `rpc.discover` is only answered here when the application
+            // did not register a handler of its own under that name.
+            None if method == DISCOVER_METHOD => Response::Success{
+                jsonrpc: request.jsonrpc(),
+                result : Some(self.openrpc()),
+                id     : request.id()
+            },
             None => Response::Error{ jsonrpc: request.jsonrpc().clone(), 
                                      error : Error::MethodNotFound, 
                                      id: request.id().clone() }
@@ -65,17 +550,52 @@ impl JsonRpc {
     }
 }
 
-unsafe impl Send for JsonRpc { }
-unsafe impl Sync for JsonRpc { }
-
 #[cfg(test)]
 mod tests {
     #[warn(non_shorthand_field_patterns)]
     #[warn(unused_imports)]
-    use super::{JsonRpc, Error, Request, Response, Json, ToJson, RpcResult, Client};
+    use super::{JsonRpc, Error, Request, Response, ResponseError, Json, ToJson, RpcResult, Client, Context, Next, Deprecation,
+                MethodInfo, ParamInfo, validate, Mode, Deviation, Version, Id, Exposure, LocalJsonRpc, Change,
+                ConcurrencyLimit, RateLimit, Middleware, Auth, Authenticator, Credentials, Identity, Requirement, Sessions,
+                HttpHandler, ClientError, Transport, TraceContext, AccessLog, LogFormat, Recorder, Exchange, Replay, Side,
+                Mock, Expectation, Params};
+    use std::collections::BTreeMap;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
     use std::str::FromStr;
     use std::string::ToString;
 
+    // Replaces the random correlation id of an internal failure in a
+    // response with `ID`, so that the response can be compared as text.
+    fn correlated(response: &str) -> String {
+        let mut json = Json::from_str(response).unwrap();
+        if let Some(&mut Json::Object(ref mut data)) = json.as_object_mut().and_then(|res| res.get_mut("error"))
+                                                           .and_then(|error| error.as_object_mut())
+                                                           .and_then(|error| error.get_mut("data")) {
+            if data.contains_key("correlation_id") {
+                data.insert("correlation_id".to_string(), "ID".to_json());
+            }
+        }
+        json.to_string()
+    }
+
+    // Collects what is written to it, for tests of writers.
+    #[derive(Clone, Default)]
+    struct Lines(Arc<Mutex<Vec<u8>>>);
+    impl Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    impl Lines {
+        fn take(&self) -> Vec<String> {
+            let lines = String::from_utf8(self.0.lock().unwrap().split_off(0)).unwrap();
+            lines.lines().map(|line| line.to_string()).collect()
+        }
+    }
     fn hello(params: &Option<Json>) -> RpcResult {
         Ok("Hello World".to_json())
     }
@@ -115,7 +635,7 @@ mod tests {
     }
     #[test]
     fn test_hello(){
-        let mut rpc = JsonRpc::new();
+        let rpc = JsonRpc::new();
         rpc.register("hello", Box::new(hello));
         let body = "{\"params\": [],       \"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": 1}";
         let req  = Request::from_str(&body).unwrap();
@@ -124,7 +644,7 @@ mod tests {
     }
     #[test]
     fn test_add(){
-        let mut rpc = JsonRpc::new();
+        let rpc = JsonRpc::new();
         rpc.register("add",   Box::new(add));
         let body = "{\"params\": [10, 20], \"jsonrpc\": \"2.0\", \"method\": \"add\",   \"id\": 2}";
         let req  = Request::from_str(&body).unwrap();
@@ -133,24 +653,1082 @@ mod tests {
     }
     #[test]
     fn test_kv(){
-        let mut rpc = JsonRpc::new();
+        let rpc = JsonRpc::new();
         rpc.register("kv",   Box::new(kv));
         let body = "{\"params\": {\"key\":\"imkey\", \"value\":\"imvalue\"}, \"jsonrpc\": \"2.0\", \"method\": \"kv\",\"id\": 3}";
         let req  = Request::from_str(&body).unwrap();
         let res  = rpc.call(&req).to_string();
         assert_eq!(&res, "{\"id\":3,\"jsonrpc\":\"2.0\",\"result\":\"imkey:imvalue\"}");
     }
-    // #[test]
+    #[test]
+    fn test_middleware(){
+        let mut rpc = JsonRpc::new();
+        rpc.register("add",   Box::new(add));
+        rpc.register("hello", Box::new(hello));
+        // Doubles every param of `add` before the handler sees it.
+        rpc.wrap_prefix("add", Box::new(|mut req: Request, ctx: &mut Context, next: Next| {
+            let params = req.params().unwrap().as_array().unwrap().iter()
+                            .map(|n| (n.as_u64().unwrap() * 2).to_json())
+                            .collect::<Vec<Json>>();
+            req.set_params(Some(Json::Array(params)));
+            next.run(req, ctx)
+        }));
+        // Rejects callers without a peer before any handler runs.
+        rpc.wrap(Box::new(|req: Request, ctx: &mut Context, next: Next| {
+            match ctx.peer() {
+                Some(_) => next.run(req, ctx),
+                None    => Response::Error{ jsonrpc: req.jsonrpc(),
+                                            error  : Error::InvalidRequest,
+                                            id     : req.id() }
+            }
+        }));
+        let body = "{\"params\": [10, 20], \"jsonrpc\": \"2.0\", \"method\": \"add\",   \"id\": 2}";
+        let req  = Request::from_str(&body).unwrap();
+        let res  = rpc.call(&req).to_string();
+        assert_eq!(&res, "{\"error\":{\"code\":-32600,\"data\":null,\"message\":\"Invalid Request\"},\"id\":2,\"jsonrpc\":\"2.0\"}");
+        let res  = rpc.call_with(&req, &mut Context::with_peer("127.0.0.1")).to_string();
+        assert_eq!(&res, "{\"id\":2,\"jsonrpc\":\"2.0\",\"result\":60}");
+        let body = "{\"params\": [],       \"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": 1}";
+        let req  = Request::from_str(&body).unwrap();
+        let res  = rpc.call_with(&req, &mut Context::with_peer("127.0.0.1")).to_string();
+        assert_eq!(&res, "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":\"Hello World\"}");
+    }
+    #[test]
+    fn test_mount(){
+        let mut math = JsonRpc::new();
+        math.register("add", Box::new(add));
+        math.wrap(Box::new(|req: Request, ctx: &mut Context, next: Next| {
+            match req.params() {
+                Some(Json::Array(_)) => next.run(req, ctx),
+                _ => Response::Error{ jsonrpc: req.jsonrpc(), error: Error::InvalidRequest, id: req.id() }
+            }
+        }));
+        let mut rpc = JsonRpc::new();
+        rpc.register("hello", Box::new(hello));
+        assert_eq!(rpc.mount("math", "/", math).is_ok(), true);
+        let misc = JsonRpc::new();
+        misc.register("add",   Box::new(add));
+        misc.register("hello", Box::new(hello));
+        assert_eq!(rpc.mount("math", "/", misc), Err(vec!["math/add".to_string()]));
+        assert_eq!(rpc.methods().keys().collect::<Vec<&String>>(), vec!["hello", "math/add"]);
+
+        let body = "{\"params\": [10, 20], \"jsonrpc\": \"2.0\", \"method\": \"math/add\", \"id\": 2}";
+        let req  = Request::from_str(&body).unwrap();
+        let res  = rpc.call(&req).to_string();
+        assert_eq!(&res, "{\"id\":2,\"jsonrpc\":\"2.0\",\"result\":30}");
+        let body = "{\"params\": {}, \"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": 1}";
+        let req  = Request::from_str(&body).unwrap();
+        let res  = rpc.call(&req).to_string();
+        assert_eq!(&res, "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":\"Hello World\"}");
+    }
+    #[test]
+    fn test_alias_and_fallback(){
+        let rpc = JsonRpc::new();
+        rpc.register("v1.add",   Box::new(add));
+        rpc.register("v2.hello", Box::new(hello));
+        rpc.alias("v1.sum", "v1.add");
+        rpc.deprecate("v1.sum", Deprecation::new().message("use v1.add").sunset("2017-01-01"));
+        rpc.fallback("v2.", "v1.");
+        assert_eq!(rpc.resolve("v2.sum").map(|(name, _)| name), Some("v1.add".to_string()));
+        assert_eq!(rpc.resolve("v2.hello").map(|(name, _)| name), Some("v2.hello".to_string()));
+        assert_eq!(rpc.resolve("v1.hello").is_none(), true);
+
+        let body = "{\"params\": [10, 20], \"jsonrpc\": \"2.0\", \"method\": \"v1.sum\", \"id\": 2}";
+        let req  = Request::from_str(&body).unwrap();
+        let mut ctx = Context::new();
+        let res  = rpc.call_with(&req, &mut ctx).to_string();
+        assert_eq!(&res, "{\"id\":2,\"jsonrpc\":\"2.0\",\"result\":30}");
+        assert_eq!(ctx.warnings(), &["method \"v1.sum\" is deprecated: use v1.add (sunset 2017-01-01)".to_string()]);
+
+        let body = "{\"params\": [10, 20], \"jsonrpc\": \"2.0\", \"method\": \"v2.add\", \"id\": 3}";
+        let req  = Request::from_str(&body).unwrap();
+        let mut ctx = Context::new();
+        let res  = rpc.call_with(&req, &mut ctx).to_string();
+        assert_eq!(&res, "{\"id\":3,\"jsonrpc\":\"2.0\",\"result\":30}");
+        assert_eq!(ctx.warnings().is_empty(), true);
+
+        // Deprecated names warn wherever they are on the way to the handler.
+        assert_eq!(rpc.route("v2.sum"), vec!["v2.sum", "v1.sum", "v1.add"]);
+        rpc.alias("old.sum", "v2.sum");
+        rpc.deprecate("old.sum", Deprecation::new());
+        let mut ctx = Context::new();
+        rpc.call_with(&Request::new("2.0", "old.sum", Some(vec![1, 2].to_json()), Some(Id::from(4))), &mut ctx);
+        assert_eq!(ctx.warnings(), &["method \"old.sum\" is deprecated".to_string(),
+                                     "method \"v1.sum\" is deprecated: use v1.add (sunset 2017-01-01)".to_string()]);
+    }
+    #[test]
+    fn test_discover(){
+        let mut rpc = JsonRpc::new();
+        rpc.register("add", Box::new(add));
+        rpc.alias("sum", "add");
+        rpc.deprecate("sum", Deprecation::new());
+        rpc.set_info("math", "1.0.0");
+        rpc.describe("add", MethodInfo::new().summary("Adds two numbers.")
+                                             .param(ParamInfo::new("a", Json::from_str("{\"type\":\"integer\"}").unwrap()))
+                                             .param(ParamInfo::new("b", Json::from_str("{\"type\":\"integer\"}").unwrap()).default(0.to_json()))
+                                             .result(Json::from_str("{\"type\":\"integer\"}").unwrap()));
+        let body = "{\"jsonrpc\": \"2.0\", \"method\": \"rpc.discover\", \"params\": [], \"id\": 1}";
+        let req  = Request::from_str(&body).unwrap();
+        let res  = rpc.call(&req).to_string();
+        let add  = "{\"name\":\"add\",\
+                     \"params\":[{\"name\":\"a\",\"required\":true,\"schema\":{\"type\":\"integer\"}},\
+                                 {\"name\":\"b\",\"required\":false,\"schema\":{\"default\":0,\"type\":\"integer\"}}],\
+                     \"result\":{\"name\":\"result\",\"schema\":{\"type\":\"integer\"}},\
+                     \"summary\":\"Adds two numbers.\"}";
+        let sum  = add.replace("\"name\":\"add\"", "\"deprecated\":true,\"name\":\"sum\"");
+        let doc  = "{\"info\":{\"title\":\"math\",\"version\":\"1.0.0\"},\
+                     \"methods\":[".to_string() + add + "," + &sum + "],\"openrpc\":\"1.2.6\"}";
+        let doc  = Json::from_str(&doc).unwrap();
+        assert_eq!(res, "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":".to_string() + &doc.to_string() + "}");
+    }
+    #[test]
+    fn test_validate(){
+        let integer = Json::from_str("{\"type\":\"integer\",\"minimum\":0}").unwrap();
+        let mut rpc = JsonRpc::new();
+        rpc.register("add", Box::new(add));
+        rpc.register("kv",  Box::new(kv));
+        rpc.describe("add", MethodInfo::new().param(ParamInfo::new("a", integer.clone()))
+                                             .param(ParamInfo::new("b", integer.clone())));
+        rpc.describe("kv",  MethodInfo::new().param(ParamInfo::new("key",   Json::from_str("{\"type\":\"string\"}").unwrap()))
+                                             .param(ParamInfo::new("value", Json::from_str("{\"type\":\"string\"}").unwrap()))
+                                             .result(integer.clone()));
+        rpc.validate_results(true);
+
+        let body = "{\"params\": [10, -20, 30], \"jsonrpc\": \"2.0\", \"method\": \"add\", \"id\": 2}";
+        let req  = Request::from_str(&body).unwrap();
+        let res  = rpc.call(&req).to_json();
+        let data = res.find_path(&["error", "data"]).unwrap().to_string();
+        assert_eq!(&data, "[{\"message\":\"must be >= 0\",\"pointer\":\"/1\"},{\"message\":\"is not allowed\",\"pointer\":\"/2\"}]");
+        let body = "{\"params\": {\"key\":\"imkey\", \"value\":\"imvalue\"}, \"jsonrpc\": \"2.0\", \"method\": \"kv\",\"id\": 3}";
+        let req  = Request::from_str(&body).unwrap();
+        let res  = correlated(&rpc.call(&req).to_string());
+        assert_eq!(&res, "{\"error\":{\"code\":-32603,\"data\":{\"correlation_id\":\"ID\",\"data\":[{\"message\":\"expected integer\",\"pointer\":\"\"}]},\"message\":\"Internal error\"},\"id\":3,\"jsonrpc\":\"2.0\"}");
+        let body = "{\"params\": [10, 20], \"jsonrpc\": \"2.0\", \"method\": \"add\", \"id\": 2}";
+        let req  = Request::from_str(&body).unwrap();
+        let res  = rpc.call(&req).to_string();
+        assert_eq!(&res, "{\"id\":2,\"jsonrpc\":\"2.0\",\"result\":30}");
+
+        // Documenting a method without declaring its params leaves them be.
+        rpc.describe("add", MethodInfo::new().summary("Adds two integers."));
+        let body = "{\"params\": [10, 20, 30], \"jsonrpc\": \"2.0\", \"method\": \"add\", \"id\": 4}";
+        let res  = rpc.call(&Request::from_str(&body).unwrap()).to_string();
+        assert_eq!(&res, "{\"id\":4,\"jsonrpc\":\"2.0\",\"result\":30}");
+    }
+    #[test]
+    fn test_schema(){
+        let schema = Json::from_str("{\"type\":\"object\",\"required\":[\"name\",\"a/b\"],\"additionalProperties\":false,\
+                                      \"properties\":{\"name\":{\"type\":\"string\",\"maxLength\":3},\
+                                                    \"tags\":{\"type\":\"array\",\"items\":{\"enum\":[\"x\",1]}},\
+                                                    \"a/b\":{\"anyOf\":[{\"type\":\"null\"},{\"const\":2}]}}}").unwrap();
+        let value  = Json::from_str("{\"name\":\"abcd\",\"tags\":[\"x\",1.0,\"y\"],\"a/b\":2.0}").unwrap();
+        let errors = validate(&schema, &value).iter().map(|e| e.pointer().to_string() + " " + e.message()).collect::<Vec<String>>();
+        assert_eq!(errors, vec!["/name must be at most 3 characters", "/tags/2 not one of the allowed values"]);
+        let value  = Json::from_str("{\"name\":1,\"extra\":true}").unwrap();
+        let errors = validate(&schema, &value).iter().map(|e| e.pointer().to_string() + " " + e.message()).collect::<Vec<String>>();
+        assert_eq!(errors, vec!["/a~1b is required", "/extra is not allowed", "/name expected string"]);
+    }
+    #[test]
+    fn test_named_params(){
+        let rpc = JsonRpc::new();
+        rpc.register_params("add", vec![ParamInfo::named("a"), ParamInfo::named("b").default(0.to_json())], Box::new(add));
+        let call = |params: &str| {
+            let body = "{\"jsonrpc\": \"2.0\", \"method\": \"add\", \"id\": 1, \"params\": ".to_string() + params + "}";
+            rpc.call(&Request::from_str(&body).unwrap()).to_json()
+        };
+        assert_eq!(call("[1, 2]").find("result"),               Some(&3u64.to_json()));
+        assert_eq!(call("{\"b\": 2, \"a\": 1}").find("result"), Some(&3u64.to_json()));
+        assert_eq!(call("{\"a\": 5}").find("result"),           Some(&5u64.to_json()));
+        assert_eq!(call("[5]").find("result"),                  Some(&5u64.to_json()));
+        assert_eq!(call("{\"a\": 1, \"c\": 2}").find_path(&["error", "data"]).unwrap().to_string(),
+                   "[{\"message\":\"is not allowed\",\"pointer\":\"/c\"}]");
+        assert_eq!(call("{\"b\": 2}").find_path(&["error", "code"]), Some(&(-32602).to_json()));
+
+        rpc.register_params("none", vec![], Box::new(hello));
+        let body = "{\"jsonrpc\": \"2.0\", \"method\": \"none\", \"id\": 1, \"params\": [1]}";
+        assert_eq!(rpc.call(&Request::from_str(body).unwrap()).to_json().find_path(&["error", "data"]).unwrap().to_string(),
+                   "[{\"message\":\"is not allowed\",\"pointer\":\"/0\"}]");
+    }
+    #[test]
+    fn test_parse(){
+        let parse = |body: &str| Request::from_str(body).map(|_| ()).map_err(|e| e.to_i64());
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": [], \"id\": 1"), Err(-32700));
+        assert_eq!(parse("[1]"),                                                              Err(-32600));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"params\": [], \"id\": 1}"),                   Err(-32600));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"method\": 1, \"params\": [], \"id\": 1}"),    Err(-32600));
+        assert_eq!(parse("{\"method\": \"hello\", \"params\": [], \"id\": 1}"),                  Err(-32600));
+        assert_eq!(parse("{\"jsonrpc\": 2, \"method\": \"hello\", \"params\": [], \"id\": 1}"),  Err(-32600));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": 1, \"id\": 1}"), Err(-32600));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": [], \"id\": 1.5}"), Err(-32600));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": [], \"id\": 18446744073709551615}"), Err(-32600));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": [], \"id\": null}"), Ok(()));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": [], \"id\": \"a1\"}"), Ok(()));
+        let req = Request::from_str("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": \"42\"}").unwrap();
+        assert_eq!(req.id(), Some(Id::from("42")));
+        assert_eq!(req.to_string(), "{\"id\":\"42\",\"jsonrpc\":\"2.0\",\"method\":\"hello\"}");
+        let req = Request::from_str("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": null}").unwrap();
+        assert_eq!((req.id(), req.is_notification()), (None, false));
+        let req = Request::from_str("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": []}").unwrap();
+        assert_eq!(req.is_notification(), true);
+        assert_eq!(req.to_string(), "{\"jsonrpc\":\"2.0\",\"method\":\"hello\",\"params\":[]}");
+    }
+    #[test]
+    fn test_trace_context(){
+        let header = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let trace = TraceContext::parse(header).unwrap();
+        assert_eq!(trace.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(trace.span_id(),  "00f067aa0ba902b7");
+        assert_eq!(trace.sampled(),  true);
+        assert_eq!(trace.to_string(), header);
+        let child = trace.child();
+        assert_eq!(child.trace_id(), trace.trace_id());
+        assert!(child.span_id() != trace.span_id());
+        assert_eq!(TraceContext::parse(&child.to_string()), Some(child));
+        assert!(TraceContext::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-later").is_some());
+        for invalid in &["00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-later",
+                         "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+                         "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+                         "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+                         "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+                         "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7"] {
+            assert_eq!(TraceContext::parse(invalid), None, "{}", invalid);
+        }
+        let new = TraceContext::new();
+        assert_eq!(TraceContext::parse(&new.to_string()), Some(new));
+
+        let req = Request::from_str(&format!("{{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": 1, \
+                                               \"traceparent\": \"{}\"}}", header)).unwrap();
+        assert_eq!(req.trace().map(|trace| trace.span_id()), Some("00f067aa0ba902b7"));
+        assert_eq!(req.to_string(), format!("{{\"id\":1,\"jsonrpc\":\"2.0\",\"method\":\"hello\",\"traceparent\":\"{}\"}}", header));
+        let req = Request::from_str("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": 1, \"traceparent\": \"nope\"}").unwrap();
+        assert_eq!(req.trace(), None);
+        let req = Request::from_str("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": 1, \"traceparent\": 1}");
+        assert_eq!(req.map(|_| ()).map_err(|e| e.to_i64()), Err(-32600));
+    }
+    #[test]
+    fn test_handle(){
+        let rpc = JsonRpc::new();
+        rpc.register("hello", Box::new(hello));
+        assert_eq!(rpc.handle("{\"jsonrpc\": \"2.0\", \"method\""),
+                   Some("{\"error\":{\"code\":-32700,\"data\":null,\"message\":\"Parse error\"},\"id\":null,\"jsonrpc\":\"2.0\"}".to_string()));
+        assert_eq!(rpc.handle("[]"),
+                   Some("{\"error\":{\"code\":-32600,\"data\":null,\"message\":\"Invalid Request\"},\"id\":null,\"jsonrpc\":\"2.0\"}".to_string()));
+        assert_eq!(rpc.handle("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": []}"), None);
+        assert_eq!(rpc.handle("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": null}"),
+                   Some("{\"id\":null,\"jsonrpc\":\"2.0\",\"result\":\"Hello World\"}".to_string()));
+        assert_eq!(rpc.handle("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": \"a1\"}"),
+                   Some("{\"id\":\"a1\",\"jsonrpc\":\"2.0\",\"result\":\"Hello World\"}".to_string()));
+        assert_eq!(rpc.handle("{\"jsonrpc\": \"2.0\", \"method\": \"nope\", \"id\": \"a2\"}"),
+                   Some("{\"error\":{\"code\":-32601,\"data\":null,\"message\":\"Method not found\"},\"id\":\"a2\",\"jsonrpc\":\"2.0\"}".to_string()));
+        let body = "[{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": [], \"id\": 1},\
+                     {\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": []},\
+                     {\"jsonrpc\": \"2.0\", \"method\": 1, \"id\": 2},\
+                     1]";
+        assert_eq!(rpc.handle(body),
+                   Some("[{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":\"Hello World\"},\
+                          {\"error\":{\"code\":-32600,\"data\":null,\"message\":\"Invalid Request\"},\"id\":2,\"jsonrpc\":\"2.0\"},\
+                          {\"error\":{\"code\":-32600,\"data\":null,\"message\":\"Invalid Request\"},\"id\":null,\"jsonrpc\":\"2.0\"}]".to_string()));
+    }
+    #[test]
+    fn test_omitted_params(){
+        let mut rpc = JsonRpc::new();
+        rpc.register("hello", Box::new(hello));
+        let body = "{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": 1}";
+        let req  = Request::from_str(&body).unwrap();
+        assert_eq!(req.params(), None);
+        assert_eq!(req.to_string(), "{\"id\":1,\"jsonrpc\":\"2.0\",\"method\":\"hello\"}");
+        assert_eq!(rpc.call(&req).to_string(), "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":\"Hello World\"}");
+
+        // `"params": null`, as sent by older clients, is only refused by
+        // servers that opt into strict parsing.
+        let body = "{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": null, \"id\": 1}";
+        assert_eq!(Request::from_str(&body).map_err(|e| e.to_i64()).err(), Some(-32600));
+        assert_eq!(Request::parse_with(Json::from_str(&body).unwrap(), Mode::Lenient).unwrap().params(), None);
+        assert_eq!(rpc.handle(&body).unwrap(), "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":\"Hello World\"}");
+        rpc.set_mode(Mode::Strict);
+        assert_eq!(rpc.handle(&body).unwrap(), "{\"error\":{\"code\":-32600,\"data\":null,\"message\":\"Invalid Request\"},\"id\":1,\"jsonrpc\":\"2.0\"}");
+    }
+    #[test]
+    fn test_lenient(){
+        let body = "{\"method\": \"hello\", \"params\": null, \"id\": \"7\", \"trace\": 1}";
+        assert_eq!(Request::from_str(&body).map_err(|e| e.to_i64()).err(), Some(-32600));
+        let (req, deviations) = Request::inspect(Json::from_str(&body).unwrap(), Mode::Lenient).unwrap();
+        assert_eq!(req.to_string(), "{\"id\":\"7\",\"jsonrpc\":\"2.0\",\"method\":\"hello\"}");
+        assert_eq!(deviations, vec![Deviation::MissingVersion,
+                                    Deviation::UnknownMember("trace".to_string()),
+                                    Deviation::NullParams]);
+        let body = "{\"jsonrpc\": \"2\", \"method\": \"hello\", \"id\": [1]}";
+        assert_eq!(Request::inspect(Json::from_str(&body).unwrap(), Mode::Lenient).map_err(|e| e.to_i64()).err(), Some(-32600));
+
+        let body = "{\"jsonrpc\": 2.0, \"result\": 1, \"id\": 1, \"extra\": true}";
+        assert_eq!(Response::from_str(&body).is_err(), true);
+        let (res, deviations) = Response::inspect(Json::from_str(&body).unwrap(), Mode::Lenient).unwrap();
+        assert_eq!(res.to_string(), "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":1}");
+        assert_eq!(deviations.iter().map(|d| d.to_string()).collect::<Vec<String>>(),
+                   vec!["\"jsonrpc\" is 2.0 instead of \"2.0\"", "unknown member \"extra\""]);
+    }
+    #[test]
+    fn test_response_errors(){
+        let parse = |body: &str| Response::from_str(body).err();
+        match parse("{\"jsonrpc\": \"2.0\", \"result\": 1") {
+            Some(ResponseError::Syntax(_)) => {},
+            err => panic!("expected a syntax error, got {:?}", err)
+        }
+        assert_eq!(parse("[]"), Some(ResponseError::NotObject));
+        assert_eq!(parse("{\"result\": 1, \"id\": 1}"), Some(ResponseError::Version(None)));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"result\": 1}"), Some(ResponseError::MissingId));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 1.5}"), Some(ResponseError::InvalidId(Json::F64(1.5))));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": \"a1\"}"), None);
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"id\": 1}"), Some(ResponseError::MissingResult));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"result\": 1, \"error\": null, \"id\": 1}"), Some(ResponseError::ResultAndError));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 1, \"x\": 0}"), Some(ResponseError::UnknownMember("x".to_string())));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32000.5, \"message\": \"x\"}, \"id\": 1}"),
+                   Some(ResponseError::InvalidError("\"code\" is not an integer")));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32000, \"message\": 1}, \"id\": 1}").map(|e| e.to_string()),
+                   Some("invalid error object: \"message\" is not a string".to_string()));
+        assert_eq!(parse("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32000}, \"id\": 1}"),
+                   Some(ResponseError::InvalidError("missing \"message\" in \"error\"")));
+
+        let res = Response::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32001.0, \"message\": \"Busy\"}, \"id\": 1}").unwrap();
+        assert_eq!(res.to_string(), "{\"error\":{\"code\":-32001,\"data\":null,\"message\":\"Busy\"},\"id\":1,\"jsonrpc\":\"2.0\"}");
+        let body = "{\"jsonrpc\": \"2.0\", \"result\": 1, \"error\": null, \"id\": 1}";
+        let (res, deviations) = Response::inspect(Json::from_str(&body).unwrap(), Mode::Lenient).unwrap();
+        assert_eq!(res.to_string(), "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":1}");
+        assert_eq!(deviations, vec![Deviation::NullMember("error".to_string())]);
+    }
+    #[test]
+    fn test_error(){
+        use std::error;
+        use rustc_serialize::json;
+
+        let err: Box<dyn error::Error> = Box::new(Error::MethodNotFound);
+        assert_eq!(err.to_string(), "Method not found (-32601)");
+        assert_eq!(Error::server(-32001, "Busy").to_string(), "Busy (-32001)");
+        assert_eq!(Error::server(-32001, "Busy"), Error::ServerError(-32001, "Busy".to_string(), None));
+        assert_eq!(Error::server(42, "Answer"), Error::Unregister(42, "Answer".to_string(), None));
+        assert_eq!(Error::from("Failed"), Error::ServerError(-32000, "Failed".to_string(), None));
+        assert_eq!(Error::invalid_params("a".to_json()).to_json().to_string(),
+                   "{\"code\":-32602,\"data\":\"a\",\"message\":\"Invalid method parameter(s)\"}");
+        assert!(Error::invalid_params("a".to_json()) != Error::InvalidParams(None));
+
+        let parse = |body: &str| -> Result<u64, Error> {
+            let json = Json::from_str(body)?;
+            Ok(json::decode::<u64>(&json.to_string())?)
+        };
+        assert_eq!(parse("{"), Err(Error::ParseError));
+        assert_eq!(parse("\"a\"").map_err(|e| e.to_i64()), Err(-32602));
+        assert_eq!(parse("1"), Ok(1));
+        assert_eq!(json::decode::<u64>("{").map_err(Error::from), Err(Error::ParseError));
+    }
+    #[test]
+    fn test_exposure(){
+        use std::io;
+
+        let mut rpc = JsonRpc::new();
+        rpc.register("hello", Box::new(hello));
+        rpc.register("add",   Box::new(add));
+        rpc.register("read",  Box::new(|_: &Option<Json>| -> Result<Json, Error> {
+            Err(Error::from_cause(&io::Error::new(io::ErrorKind::NotFound, "config.toml")))
+        }));
+        rpc.alias("load", "read");
+        let call = |rpc: &JsonRpc, method: &str| -> Json {
+            let req = Request::new("2.0", method, None, Some(Id::from(1)));
+            rpc.call(&req).to_json().find("error").unwrap().clone()
+        };
+        // Even as the handler returned it, a failure carries the id it was
+        // logged with.
+        let error = call(&rpc, "add");
+        assert_eq!(error.find("message"), Some(&"参数错误。".to_json()));
+        assert_eq!(error.find_path(&["data", "correlation_id"]).and_then(|id| id.as_string()).map(|id| id.len()), Some(32));
+        let error = call(&rpc, "load");
+        assert!(error.find_path(&["data", "correlation_id"]).is_some());
+        assert_eq!(error.find_path(&["data", "causes"]), Some(&vec!["config.toml".to_json()].to_json()));
+
+        rpc.set_exposure(Exposure::Minimal);
+        let error = call(&rpc, "add");
+        assert_eq!(error.find("message"), Some(&"Server error".to_json()));
+        let data = error.find("data").unwrap().as_object().unwrap();
+        assert_eq!(data.keys().collect::<Vec<&String>>(), vec!["correlation_id"]);
+        assert_eq!(data["correlation_id"].as_string().unwrap().len(), 32);
+        assert!(call(&rpc, "load").find_path(&["data", "correlation_id"]).is_some());
+        assert_eq!(call(&rpc, "load").find_path(&["data", "causes"]), None);
+        // Protocol errors are not internal failures.
+        assert_eq!(call(&rpc, "nope").to_string(), "{\"code\":-32601,\"data\":null,\"message\":\"Method not found\"}");
+
+        rpc.set_exposure(Exposure::Full);
+        let error = call(&rpc, "load");
+        assert_eq!(error.find("code"), Some(&(-32603).to_json()));
+        assert_eq!(error.find_path(&["data", "handler"]), Some(&"read".to_json()));
+        assert_eq!(error.find_path(&["data", "data", "causes"]), Some(&vec!["config.toml".to_json()].to_json()));
+        assert!(error.find_path(&["data", "correlation_id"]).is_some());
+        assert_eq!(call(&rpc, "add").find("message"), Some(&"参数错误。".to_json()));
+    }
+    #[test]
+    fn test_catch_panics(){
+        let mut rpc = JsonRpc::new();
+        rpc.register("add", Box::new(add));
+        rpc.catch_panics(true);
+        // `add` unwraps its params.
+        let body = "{\"jsonrpc\": \"2.0\", \"method\": \"add\", \"params\": [1, \"2\"], \"id\": 1}";
+        assert_eq!(correlated(&rpc.handle(body).unwrap()),
+                   "{\"error\":{\"code\":-32603,\"data\":{\"correlation_id\":\"ID\"},\"message\":\"Internal error\"},\"id\":1,\"jsonrpc\":\"2.0\"}");
+        assert_eq!(rpc.panics(), 1);
+
+        let body = "{\"jsonrpc\": \"2.0\", \"method\": \"add\", \"params\": [1, 2], \"id\": 2}";
+        assert_eq!(rpc.handle(body).unwrap(), "{\"id\":2,\"jsonrpc\":\"2.0\",\"result\":3}");
+
+        rpc.set_exposure(Exposure::Full);
+        let body = "{\"jsonrpc\": \"2.0\", \"method\": \"add\", \"params\": [\"1\", 2], \"id\": 3}";
+        let res = Json::from_str(&rpc.handle(body).unwrap()).unwrap();
+        assert_eq!(res.find_path(&["error", "data", "data", "panic"]),
+                   Some(&"called `Option::unwrap()` on a `None` value".to_json()));
+        assert_eq!(rpc.panics(), 2);
+    }
+    #[test]
+    fn test_local(){
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use std::sync::Arc;
+        use std::thread;
+
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+        let rpc = JsonRpc::new();
+        rpc.register("hello", Box::new(hello));
+        assert_send_sync(&rpc);
+        let rpc = Arc::new(rpc);
+        let shared = rpc.clone();
+        let res = thread::spawn(move || shared.handle("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": 1}"));
+        assert_eq!(res.join().unwrap().unwrap(), "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":\"Hello World\"}");
+
+        let count = Rc::new(Cell::new(0u64));
+        let counter = count.clone();
+        let mut local = LocalJsonRpc::new();
+        local.register("count", Box::new(move |_: &Option<Json>| -> RpcResult {
+            counter.set(counter.get() + 1);
+            Ok(counter.get().to_json())
+        }));
+        local.rpc_mut().register("hello", Box::new(hello));
+        local.rpc_mut().alias("inc", "count");
+        assert_eq!(local.handle("{\"jsonrpc\": \"2.0\", \"method\": \"count\", \"id\": 1}").unwrap(),
+                   "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":1}");
+        assert_eq!(local.handle("{\"jsonrpc\": \"2.0\", \"method\": \"inc\", \"id\": 2}").unwrap(),
+                   "{\"id\":2,\"jsonrpc\":\"2.0\",\"result\":2}");
+        assert_eq!(local.handle("{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": 3}").unwrap(),
+                   "{\"id\":3,\"jsonrpc\":\"2.0\",\"result\":\"Hello World\"}");
+        // The wrapped dispatcher alone can not reach local handlers.
+        assert_eq!(correlated(&local.rpc().handle("{\"jsonrpc\": \"2.0\", \"method\": \"count\", \"id\": 4}").unwrap()),
+                   "{\"error\":{\"code\":-32603,\"data\":{\"correlation_id\":\"ID\"},\"message\":\"Internal error\"},\"id\":4,\"jsonrpc\":\"2.0\"}");
+        assert_eq!(count.get(), 2);
+    }
+    #[test]
+    fn test_hot_reload(){
+        use std::sync::Arc;
+        use std::thread;
+
+        let rpc = Arc::new(JsonRpc::new());
+        let changes = rpc.watch();
+        let call = |rpc: &JsonRpc, method: &str| rpc.call(&Request::new("2.0", method, None, Some(Id::from(1)))).to_json();
+
+        let shared = rpc.clone();
+        thread::spawn(move || shared.register("hello", Box::new(hello))).join().unwrap();
+        assert_eq!(call(&rpc, "hello").find("result"), Some(&"Hello World".to_json()));
+        assert_eq!(rpc.replace("hello", Box::new(|_: &Option<Json>| -> RpcResult { Ok("Hi".to_json()) })), true);
+        assert_eq!(call(&rpc, "hello").find("result"), Some(&"Hi".to_json()));
+        assert_eq!(rpc.replace("bye", Box::new(hello)), false);
+
+        // A call in flight finishes with the handler it started with.
+        let shared = rpc.clone();
+        rpc.register("once", Box::new(move |_: &Option<Json>| -> RpcResult {
+            shared.unregister("once");
+            Ok("done".to_json())
+        }));
+        assert_eq!(call(&rpc, "once").find("result"), Some(&"done".to_json()));
+        assert_eq!(call(&rpc, "once").find_path(&["error", "code"]), Some(&(-32601).to_json()));
+        assert_eq!(rpc.unregister("once"), false);
+
+        assert_eq!(changes.try_iter().collect::<Vec<Change>>(),
+                   vec![Change::Registered("hello".to_string()),
+                        Change::Replaced("hello".to_string()),
+                        Change::Registered("once".to_string()),
+                        Change::Unregistered("once".to_string())]);
+
+        // Plugins declaring params, aliases and deprecations load into a
+        // dispatcher that is already serving.
+        let shared = rpc.clone();
+        thread::spawn(move || {
+            shared.register_params("sum", vec![ParamInfo::named("a"), ParamInfo::named("b")], Box::new(add));
+            shared.alias("plus", "sum");
+            shared.deprecate("plus", Deprecation::default());
+            shared.describe("hello", MethodInfo::new().summary("Greets"));
+        }).join().unwrap();
+        let res = rpc.call(&Request::new("2.0", "plus", Some(Json::from_str("{\"b\": 2, \"a\": 1}").unwrap()), Some(Id::from(1))));
+        assert_eq!(res.to_json().find("result"), Some(&3u64.to_json()));
+        assert_eq!(rpc.description("hello").and_then(|info| info.get_summary().map(|s| s.to_string())), Some("Greets".to_string()));
+
+        // A method registered again after `unregister` starts without the
+        // params declared for the old one.
+        assert_eq!(rpc.unregister("sum"), true);
+        assert!(rpc.description("sum").is_none());
+        rpc.register("sum", Box::new(|params: &Option<Json>| -> RpcResult { Ok(params.to_json()) }));
+        let res = rpc.call(&Request::new("2.0", "sum", Some(vec![1, 2, 3].to_json()), Some(Id::from(1))));
+        assert_eq!(res.to_json().find("result").map(|result| result.to_string()), Some("[1,2,3]".to_string()));
+        let res = rpc.call(&Request::new("2.0", "sum", Some(Json::from_str("{\"b\": 2}").unwrap()), Some(Id::from(1))));
+        assert_eq!(res.to_json().find("result").map(|result| result.to_string()), Some("{\"b\":2}".to_string()));
+
+        // Documents are built while methods come and go.
+        let shared = rpc.clone();
+        let churn = thread::spawn(move || for _ in 0 .. 20000 {
+            shared.register("churn", Box::new(hello));
+            shared.unregister("churn");
+        });
+        for _ in 0 .. 20000 {
+            assert!(rpc.openrpc().find("methods").is_some());
+        }
+        churn.join().unwrap();
+    }
+    #[test]
+    fn test_concurrency_limit(){
+        use std::sync::{Arc, Mutex};
+        use std::sync::mpsc::channel;
+        use std::thread;
+        use std::time::Duration;
+
+        let (started, wait_started) = channel();
+        let (finish, wait_finish) = channel::<()>();
+        let (started, wait_finish) = (Mutex::new(started), Mutex::new(wait_finish));
+        let mut rpc = JsonRpc::new();
+        rpc.register("report.generate", Box::new(move |_: &Option<Json>| -> RpcResult {
+            started.lock().unwrap().send(()).unwrap();
+            wait_finish.lock().unwrap().recv().unwrap();
+            Ok("report".to_json())
+        }));
+        rpc.register("report.list", Box::new(hello));
+        rpc.alias("generate", "report.generate");
+        rpc.wrap_prefix("report.", Box::new(ConcurrencyLimit::new(1).code(-32050).retry_after(2.5)));
+        let rpc = Arc::new(rpc);
+        let call = |rpc: &JsonRpc, method: &str| rpc.call(&Request::new("2.0", method, None, Some(Id::from(1)))).to_json();
+
+        let shared = rpc.clone();
+        let running = thread::spawn(move || call(&shared, "report.generate"));
+        wait_started.recv().unwrap();
+        assert_eq!(call(&rpc, "report.generate").find("error").unwrap().to_string(),
+                   "{\"code\":-32050,\"data\":{\"retry_after\":2.5},\"message\":\"Too many concurrent calls\"}");
+        // The cap is per method, aliases outside the prefix included.
+        assert_eq!(call(&rpc, "generate").find_path(&["error", "code"]), Some(&(-32050).to_json()));
+        assert_eq!(call(&rpc, "report.list").find("result"), Some(&"Hello World".to_json()));
+        finish.send(()).unwrap();
+        assert_eq!(running.join().unwrap().find("result"), Some(&"report".to_json()));
+
+        // A queued call waits until the running one releases its slot.
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (started, wait_started) = channel();
+        let (finish, wait_finish) = channel::<()>();
+        let (started, wait_finish) = (Mutex::new(started), Mutex::new(wait_finish));
+        let mut rpc = JsonRpc::new();
+        let log = events.clone();
+        rpc.register("slow", Box::new(move |params: &Option<Json>| -> RpcResult {
+            let n = params.as_ref().unwrap()[0].as_u64().unwrap();
+            log.lock().unwrap().push(format!("start {}", n));
+            if n == 1 {
+                started.lock().unwrap().send(()).unwrap();
+                wait_finish.lock().unwrap().recv().unwrap();
+            }
+            log.lock().unwrap().push(format!("end {}", n));
+            Ok(Json::Null)
+        }));
+        let limit = Arc::new(ConcurrencyLimit::new(1).queue(Duration::from_secs(10)));
+        let shared = limit.clone();
+        rpc.wrap(Box::new(move |request: Request, ctx: &mut Context, next: Next| shared.handle(request, ctx, next)));
+        let rpc = Arc::new(rpc);
+        let slow = |rpc: &JsonRpc, n: u64| rpc.call(&Request::new("2.0", "slow", Some(vec![n].to_json()), Some(Id::from(1)))).to_json();
+        let shared = rpc.clone();
+        let first = thread::spawn(move || slow(&shared, 1));
+        wait_started.recv().unwrap();
+        let (done, wait_done) = channel();
+        let shared = rpc.clone();
+        let second = thread::spawn(move || {
+            let response = slow(&shared, 2);
+            done.send(()).unwrap();
+            response
+        });
+        assert!(wait_done.recv_timeout(Duration::from_millis(50)).is_err());
+        assert_eq!(*events.lock().unwrap(), vec!["start 1"]);
+        assert_eq!(limit.running("slow"), 1);
+        finish.send(()).unwrap();
+        assert_eq!(first.join().unwrap().find("error"), None);
+        assert_eq!(second.join().unwrap().find("error"), None);
+        assert_eq!(*events.lock().unwrap(), vec!["start 1", "end 1", "start 2", "end 2"]);
+        assert_eq!(limit.running("slow"), 0);
+    }
+    #[test]
+    fn test_rate_limit(){
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let mut rpc = JsonRpc::new();
+        rpc.register("hello", Box::new(hello));
+        rpc.wrap(Box::new(RateLimit::new(2, Duration::from_secs(3600))));
+        let call = |rpc: &JsonRpc, peer: &str| {
+            rpc.call_with(&Request::new("2.0", "hello", None, Some(Id::from(1))), &mut Context::with_peer(peer)).to_json()
+        };
+        // Callers are told apart by their address, not the port they
+        // happen to connect from.
+        assert_eq!(call(&rpc, "10.0.0.1:4000").find("result"), Some(&"Hello World".to_json()));
+        assert_eq!(call(&rpc, "10.0.0.1:4001").find("result"), Some(&"Hello World".to_json()));
+        let error = call(&rpc, "10.0.0.1:4002");
+        assert_eq!(error.find_path(&["error", "code"]), Some(&(-32029).to_json()));
+        let retry_after = error.find_path(&["error", "data", "retry_after"]).unwrap().as_f64().unwrap();
+        assert!(retry_after > 1790.0 && retry_after <= 1800.0);
+        assert_eq!(call(&rpc, "10.0.0.2:4000").find("result"), Some(&"Hello World".to_json()));
+        assert_eq!(call(&rpc, "[::1]:4000").find("result"), Some(&"Hello World".to_json()));
+        // Aliases share the buckets of their target.
+        rpc.alias("hi", "hello");
+        assert_eq!(call(&rpc, "[::1]:4001").find("result"), Some(&"Hello World".to_json()));
+        let request = Request::new("2.0", "hi", None, Some(Id::from(1)));
+        assert!(rpc.call_with(&request, &mut Context::with_peer("[::1]:4002")).to_json().find("error").is_some());
+
+        // Authenticated callers are limited wherever they connect from.
+        let mut rpc = JsonRpc::new();
+        rpc.register("hello", Box::new(hello));
+        rpc.wrap(Box::new(RateLimit::new(1, Duration::from_secs(3600))));
+        let mut ctx = Context::with_peer("10.0.0.1:4000");
+        ctx.set_identity(Identity::new("alice"));
+        assert_eq!(rpc.call_with(&Request::new("2.0", "hello", None, Some(Id::from(1))), &mut ctx).to_json().find("error"), None);
+        let mut ctx = Context::with_peer("10.0.0.2:4000");
+        ctx.set_identity(Identity::new("alice"));
+        assert!(rpc.call_with(&Request::new("2.0", "hello", None, Some(Id::from(1))), &mut ctx).to_json().find("error").is_some());
+        assert_eq!(call(&rpc, "10.0.0.2:4000").find("error"), None);
+
+        // Buckets that have refilled are forgotten.
+        let mut rpc = JsonRpc::new();
+        rpc.register("hello", Box::new(hello));
+        let limit = Arc::new(RateLimit::new(1, Duration::from_millis(20)));
+        let shared = limit.clone();
+        rpc.wrap(Box::new(move |request: Request, ctx: &mut Context, next: Next| shared.handle(request, ctx, next)));
+        call(&rpc, "10.0.0.1:4000");
+        call(&rpc, "10.0.0.2:4000");
+        assert_eq!(limit.buckets(), 2);
+        ::std::thread::sleep(Duration::from_millis(50));
+        call(&rpc, "10.0.0.3:4000");
+        assert_eq!(limit.buckets(), 1);
+
+        let mut rpc = JsonRpc::new();
+        rpc.register("hello", Box::new(hello));
+        rpc.wrap(Box::new(RateLimit::new(1, Duration::from_secs(3600)).key(|_: &Request, ctx: &Context| {
+            ctx.get("user").map(|user| user.to_string()).unwrap_or_default()
+        })));
+        let mut ctx = Context::with_peer("10.0.0.1");
+        ctx.set("user", "alice".to_json());
+        assert_eq!(rpc.call_with(&Request::new("2.0", "hello", None, Some(Id::from(1))), &mut ctx).to_json().find("error"), None);
+        let mut ctx = Context::with_peer("10.0.0.2");
+        ctx.set("user", "alice".to_json());
+        assert!(rpc.call_with(&Request::new("2.0", "hello", None, Some(Id::from(1))), &mut ctx).to_json().find("error").is_some());
+    }
+    #[test]
+    fn test_auth(){
+        use std::sync::Arc;
+
+        assert_eq!(Credentials::from_header("Bearer abc"), Some(Credentials::Bearer("abc".to_string())));
+        assert_eq!(Credentials::from_headers(vec![("Host", "x"), ("authorization", "Basic dXNlcjpwYXNz")]),
+                   Some(Credentials::Basic{ user: "user".to_string(), password: "pass".to_string() }));
+        assert_eq!(Credentials::from_header("Basic !!"), None);
+        assert_eq!(Credentials::from_header("Digest abc"), None);
+
+        let mut rpc = JsonRpc::new();
+        rpc.register("hello",        Box::new(hello));
+        rpc.register("admin.hello",  Box::new(hello));
+        rpc.register("report.hello", Box::new(hello));
+        rpc.alias("greet", "admin.hello");
+        let sessions = Arc::new(Sessions::new());
+        let passwords = |credentials: &Credentials| match *credentials {
+            Credentials::Basic{ ref user, ref password } if user == "root" && password == "secret" => {
+                Some(Identity::new("root").role("admin"))
+            },
+            Credentials::Basic{ ref user, ref password } if user == "bob" && password == "hunter2" => {
+                Some(Identity::new("bob").scope("reports"))
+            },
+            _ => None
+        };
+        rpc.register_login("auth.login", sessions.clone(), Box::new(passwords));
+        let shared = sessions.clone();
+        rpc.wrap(Box::new(Auth::new(Box::new(move |credentials: &Credentials| shared.authenticate(credentials)))
+                              .require("admin.",  Requirement::role("admin"))
+                              .require("report.", Requirement::scope("reports"))));
+
+        let call = |method: &str, params: Option<Json>, credentials: Option<Credentials>| -> Json {
+            let mut ctx = Context::new();
+            if let Some(credentials) = credentials {
+                ctx.set_credentials(credentials);
+            }
+            rpc.call_with(&Request::new("2.0", method, params, Some(Id::from(1))), &mut ctx).to_json()
+        };
+        let params = |user: &str, password: &str| Some(vec![user.to_json(), password.to_json()].to_json());
+        assert_eq!(call("hello", None, None).find("result"), Some(&"Hello World".to_json()));
+        assert_eq!(call("admin.hello", None, None).find("error").unwrap().to_string(),
+                   "{\"code\":-32001,\"data\":null,\"message\":\"Unauthenticated\"}");
+        assert_eq!(call("auth.login", params("root", "wrong"), None).find_path(&["error", "code"]), Some(&(-32001).to_json()));
+
+        let root = call("auth.login", params("root", "secret"), None).find("result").unwrap().as_string().unwrap().to_string();
+        let bob  = call("auth.login", params("bob", "hunter2"), None).find("result").unwrap().as_string().unwrap().to_string();
+        assert_eq!((root.len(), bob.len()), (64, 64));
+        assert!(root != bob);
+        let root = Some(Credentials::Bearer(root));
+        let bob  = Some(Credentials::Bearer(bob));
+        assert_eq!(call("admin.hello", None, root.clone()).find("result"), Some(&"Hello World".to_json()));
+        assert_eq!(call("admin.hello", None, bob.clone()).find("error").unwrap().to_string(),
+                   "{\"code\":-32003,\"data\":\"role admin\",\"message\":\"Forbidden\"}");
+        assert_eq!(call("report.hello", None, bob.clone()).find("result"), Some(&"Hello World".to_json()));
+        assert_eq!(call("report.hello", None, root.clone()).find_path(&["error", "code"]), Some(&(-32003).to_json()));
+        // An alias does not get around the requirements of its target.
+        assert_eq!(call("greet", None, None).find_path(&["error", "code"]), Some(&(-32001).to_json()));
+        assert_eq!(call("greet", None, bob.clone()).find_path(&["error", "code"]), Some(&(-32003).to_json()));
+        assert_eq!(call("greet", None, root.clone()).find("result"), Some(&"Hello World".to_json()));
+
+        if let Some(Credentials::Bearer(ref token)) = root {
+            assert_eq!(sessions.logout(token), true);
+        }
+        assert_eq!(call("admin.hello", None, root).find_path(&["error", "code"]), Some(&(-32001).to_json()));
+    }
+    #[test]
+    fn test_http(){
+        extern crate hyper;
+        use std::sync::Arc;
+
+        let mut rpc = JsonRpc::new();
+        rpc.register("hello", Box::new(hello));
+        rpc.register("whoami", Box::new(hello));
+        rpc.wrap(Box::new(Auth::new(Box::new(|credentials: &Credentials| match *credentials {
+            Credentials::Bearer(ref token) if token == "t0ken" => Some(Identity::new("alice")),
+            _ => None
+        })).require("whoami", Requirement::Authenticated)));
+        let mut server = hyper::Server::http("127.0.0.1:0").unwrap()
+                                                           .handle(HttpHandler::new(Arc::new(rpc))).unwrap();
+        let mut client = Client::new(&format!("http://{}", server.socket)).unwrap();
+        assert_eq!(client.request("hello", &None, &1), Ok(Some("Hello World".to_json())));
+        match client.request("whoami", &None, &2) {
+            Err(ClientError::Remote(error)) => assert_eq!(error.to_i64(), -32001),
+            result => panic!("expected a remote error, got {:?}", result)
+        }
+        client.set_authorization("Bearer t0ken");
+        assert_eq!(client.request("whoami", &None, &3), Ok(Some("Hello World".to_json())));
+        server.close().unwrap();
+    }
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics(){
+        extern crate hyper;
+        use std::io::Read;
+        use std::sync::Arc;
+        use super::UNKNOWN_METHOD;
+
+        let rpc = JsonRpc::new();
+        rpc.register("add", Box::new(add));
+        rpc.register("fail", Box::new(|_: &Option<Json>| Err("no")));
+        rpc.handle("[{\"jsonrpc\": \"2.0\", \"method\": \"add\", \"params\": [1, 2], \"id\": 1},\
+                     {\"jsonrpc\": \"2.0\", \"method\": \"fail\", \"id\": 2},\
+                     {\"jsonrpc\": \"2.0\", \"method\": \"nope\", \"id\": 3}]");
+        let metrics = rpc.metrics().clone();
+        assert_eq!(metrics.get("jsonrpc_server_requests_total", &[("method", "add")]), Some(1.0));
+        assert_eq!(metrics.get("jsonrpc_server_requests_total", &[("method", "nope")]), None);
+        assert_eq!(metrics.get("jsonrpc_server_errors_total", &[("method", UNKNOWN_METHOD), ("code", "-32601")]), Some(1.0));
+        assert_eq!(metrics.get("jsonrpc_server_errors_total", &[("method", "fail"), ("code", "-32000")]), Some(1.0));
+        assert_eq!(metrics.get("jsonrpc_server_in_flight", &[("method", "add")]), Some(0.0));
+        assert_eq!(metrics.observed("jsonrpc_server_batch_size", &[]), Some((1, 3.0)));
+        assert_eq!(metrics.observed("jsonrpc_server_request_duration_seconds", &[("method", "add")]).map(|o| o.0), Some(1));
+        assert_eq!(metrics.observed("jsonrpc_server_response_bytes", &[("method", "fail")]).map(|o| o.0), Some(1));
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE jsonrpc_server_requests_total counter\n"));
+        assert!(text.contains("jsonrpc_server_requests_total{method=\"add\"} 1\n"));
+        assert!(text.contains("jsonrpc_server_batch_size_bucket{le=\"2\"} 0\n"));
+        assert!(text.contains("jsonrpc_server_batch_size_bucket{le=\"5\"} 1\n"));
+        assert!(text.contains("jsonrpc_server_batch_size_bucket{le=\"+Inf\"} 1\n"));
+        assert!(text.contains("jsonrpc_server_request_duration_seconds_count{method=\"add\"} 1\n"));
+
+        let mut server = hyper::Server::http("127.0.0.1:0").unwrap()
+                                                           .handle(HttpHandler::new(Arc::new(rpc)).serve_metrics("/metrics"))
+                                                           .unwrap();
+        let uri = format!("http://{}", server.socket);
+        let mut client = Client::new(&uri).unwrap();
+        client.set_metrics(metrics.clone());
+        assert_eq!(client.request("add", &Some(vec![2, 3].to_json()), &4), Ok(Some(5u64.to_json())));
+        assert_eq!(metrics.get("jsonrpc_client_requests_total", &[("method", "add")]), Some(1.0));
+        assert_eq!(metrics.get("jsonrpc_server_requests_total", &[("method", "add")]), Some(2.0));
+
+        let mut text = String::new();
+        hyper::Client::new().get(&(uri + "/metrics")).send().unwrap().read_to_string(&mut text).unwrap();
+        assert!(text.contains("jsonrpc_client_request_duration_seconds_count{method=\"add\"} 1\n"));
+        server.close().unwrap();
+
+        let unreachable = Client::new("http://127.0.0.1:1").unwrap();
+        assert!(unreachable.request("add", &None, &5).is_err());
+        assert_eq!(unreachable.metrics().get("jsonrpc_client_failures_total", &[("method", "add"), ("reason", "request")]),
+                   Some(1.0));
+    }
+    #[test]
+    fn test_access_log(){
+        let lines = Lines::default();
+        let mut rpc = JsonRpc::new();
+        rpc.register("add", Box::new(add));
+        rpc.register("login", Box::new(|_: &Option<Json>| Ok("t0ken".to_json()) as RpcResult));
+        rpc.wrap(Box::new(AccessLog::new(LogFormat::JsonLines)
+                              .params(true)
+                              .writer(Box::new(lines.clone()))
+                              .redact("login", "password")
+                              .redact("login", "devices.*.pin")
+                              .redact("add", "1")));
+        let mut ctx = Context::with_peer("10.0.0.1:4000");
+        rpc.handle_with("{\"jsonrpc\": \"2.0\", \"method\": \"login\", \"id\": 1, \"params\": \
+                         {\"user\": \"bob\", \"password\": \"hunter2\", \"devices\": [{\"pin\": 1234, \"name\": \"phone\"}]}}",
+                        &mut ctx);
+        rpc.handle_with("[{\"jsonrpc\": \"2.0\", \"method\": \"add\", \"params\": [1, 2], \"id\": 2},\
+                          {\"jsonrpc\": \"2.0\", \"method\": \"nope\"}]", &mut ctx);
+        let logged = lines.take().iter().map(|line| Json::from_str(line).unwrap()).collect::<Vec<Json>>();
+        assert_eq!(logged.len(), 3);
+        assert_eq!(logged[0].find("peer"), Some(&"10.0.0.1:4000".to_json()));
+        assert_eq!(logged[0].find("method"), Some(&"login".to_json()));
+        assert_eq!(logged[0].find("id").and_then(|id| id.as_i64()), Some(1));
+        assert_eq!(logged[0].find("outcome"), Some(&"result".to_json()));
+        assert_eq!(logged[0].find("params_bytes").and_then(|bytes| bytes.as_i64()), Some(75));
+        assert_eq!(logged[0].find("params").unwrap().to_string(),
+                   "{\"devices\":[{\"name\":\"phone\",\"pin\":\"[REDACTED]\"}],\"password\":\"[REDACTED]\",\"user\":\"bob\"}");
+        assert!(logged[0].find("latency_ms").unwrap().as_f64().unwrap() >= 0.0);
+        let ts = logged[0].find("ts").unwrap().as_string().unwrap();
+        assert_eq!((ts.len(), &ts[4 .. 5], &ts[10 .. 11], &ts[23 ..]), (24, "-", "T", "Z"));
+        assert_eq!(logged[1].find("params").unwrap().to_string(), "[1,\"[REDACTED]\"]");
+        assert_eq!(logged[2].find("id"), Some(&Json::Null));
+        assert_eq!(logged[2].find("outcome"), Some(&"error".to_json()));
+        assert_eq!(logged[2].find("code"), Some(&(-32601).to_json()));
+
+        let lines = Lines::default();
+        let mut rpc = JsonRpc::new();
+        rpc.register("add", Box::new(add));
+        rpc.wrap(Box::new(AccessLog::new(LogFormat::Logfmt).writer(Box::new(lines.clone()))));
+        rpc.call(&Request::new("2.0", "add", Some(vec![1, 2].to_json()), Some(Id::from(3))));
+        rpc.call(&Request::new("2.0", "nope", None, Some(Id::from(4))));
+        let logged = lines.take();
+        assert!(logged[0].starts_with("ts=20"));
+        assert!(logged[0].contains(" peer=\"\" method=add id=3 params_bytes=5 latency_ms="));
+        assert!(logged[0].ends_with(" outcome=result"));
+        assert!(logged[1].ends_with(" outcome=error code=-32601"));
+        assert!(!logged[1].contains("params="));
+
+        // Control characters can not break or forge lines.
+        rpc.call(&Request::new("2.0", "a\r\tb\u{1b}[0m\n ts=0", None, Some(Id::from(5))));
+        let logged = lines.take();
+        assert_eq!(logged.len(), 1);
+        assert!(logged[0].contains(" method=\"a\\r\\tb\\u{001b}[0m\\n ts=0\" id=5 "));
+
+        // Calls through an alias are redacted as the method serving them.
+        let lines = Lines::default();
+        let mut rpc = JsonRpc::new();
+        rpc.register("auth.login", Box::new(|_: &Option<Json>| Ok("t0ken".to_json()) as RpcResult));
+        rpc.alias("login", "auth.login");
+        rpc.wrap(Box::new(AccessLog::new(LogFormat::Logfmt)
+                              .params(true)
+                              .writer(Box::new(lines.clone()))
+                              .redact("auth.login", "password")));
+        rpc.call(&Request::new("2.0", "login", Some(Json::from_str("{\"password\": \"hunter2\"}").unwrap()), Some(Id::from(6))));
+        let logged = lines.take();
+        assert!(logged[0].contains(" method=login "));
+        assert!(logged[0].ends_with(" params=\"{\\\"password\\\":\\\"[REDACTED]\\\"}\""));
+    }
+    #[test]
+    fn test_record(){
+        let lines = Lines::default();
+        let mut rpc = JsonRpc::new();
+        rpc.register("add", Box::new(add));
+        rpc.register("hello", Box::new(hello));
+        rpc.record(Arc::new(Recorder::new(Box::new(lines.clone()))));
+        let mut ctx = Context::with_peer("10.0.0.1:4000");
+        rpc.handle_with("{\"jsonrpc\": \"2.0\", \"method\": \"add\", \"params\": [1, 2], \"id\": 1}", &mut ctx);
+        rpc.handle_with("[{\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"id\": 2},\
+                          {\"jsonrpc\": \"2.0\", \"method\": \"add\", \"params\": [2, 2], \"id\": 3}]", &mut ctx);
+        rpc.handle_with("{\"jsonrpc\": \"2.0\", \"method\": \"hello\"}", &mut ctx);
+        rpc.handle_with("{oops", &mut ctx);
+
+        let recorded = lines.take().join("\n");
+        let exchanges = Exchange::read(recorded.as_bytes()).unwrap();
+        assert_eq!(exchanges.len(), 4);
+        assert_eq!(exchanges[0].side(), Side::Server);
+        assert_eq!(exchanges[0].peer(), Some("10.0.0.1:4000"));
+        assert_eq!(exchanges[0].raw_request(), "{\"jsonrpc\": \"2.0\", \"method\": \"add\", \"params\": [1, 2], \"id\": 1}");
+        assert_eq!(exchanges[0].raw_response(), Some("{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":3}"));
+        assert_eq!(exchanges[0].requests()[0].as_ref().map(|request| request.method()), Ok("add".to_string()));
+        assert_eq!(exchanges[0].time().len(), 24);
+        assert_eq!(exchanges[1].requests().len(), 2);
+        assert_eq!(exchanges[1].responses().len(), 2);
+        assert_eq!(exchanges[2].raw_response(), None);
+        assert_eq!(exchanges[3].raw_request(), "{oops");
+        let line = Json::from_str(recorded.lines().next().unwrap()).unwrap();
+        assert_eq!(line.find_path(&["request", "method"]), Some(&"add".to_json()));
+        assert_eq!(line.find_path(&["response", "result"]).and_then(|result| result.as_i64()), Some(3));
+
+        // The same dispatcher answers the same, without recording again.
+        assert_eq!(Replay::new(&rpc).run(&exchanges), vec![]);
+        assert!(lines.take().is_empty());
+
+        // A change in behavior shows up where the responses differ.
+        let changed = JsonRpc::new();
+        changed.register("add", Box::new(|params: &Option<Json>| -> RpcResult {
+            let params = params.as_ref().unwrap();
+            Ok((params[0].as_i64().unwrap() + params[1].as_i64().unwrap() + 1).to_json())
+        }));
+        changed.register("hello", Box::new(hello));
+        let mismatches = Replay::new(&changed).run(&exchanges);
+        assert_eq!(mismatches.iter().map(|mismatch| mismatch.index).collect::<Vec<usize>>(), vec![0, 1]);
+        assert_eq!(mismatches[0].differences, vec!["result: expected 3, got 4"]);
+        assert_eq!(mismatches[1].differences, vec!["1.result: expected 4, got 5"]);
+        assert_eq!(mismatches[0].to_string(), format!("exchange 0: {}\n  result: expected 3, got 4", exchanges[0].raw_request()));
+        assert_eq!(Replay::new(&changed).ignore("result").ignore("*.result").run(&exchanges), vec![]);
+
+        let missing = JsonRpc::new();
+        missing.register("hello", Box::new(hello));
+        let mismatches = Replay::new(&missing).run(&exchanges[.. 1]);
+        assert_eq!(mismatches[0].differences, vec!["result: expected 3, got nothing",
+                                                   "error: expected nothing, got {\"code\":-32601,\"data\":null,\"message\":\"Method not found\"}"]);
+
+        // Redacted params stay out of the recording.
+        let lines = Lines::default();
+        let mut logins = JsonRpc::new();
+        logins.register("login", Box::new(|_: &Option<Json>| Ok("t0ken".to_json()) as RpcResult));
+        logins.register("hello", Box::new(hello));
+        logins.alias("signin", "login");
+        logins.fallback("v2.", "");
+        logins.record(Arc::new(Recorder::new(Box::new(lines.clone())).redact("login", "password")));
+        logins.handle("[{\"jsonrpc\": \"2.0\", \"method\": \"login\", \"params\": {\"user\": \"bob\", \"password\": \"hunter2\"}, \"id\": 1},\
+                       {\"jsonrpc\": \"2.0\", \"method\": \"hello\", \"params\": {\"password\": \"kept\"}, \"id\": 2}]");
+        logins.handle("{\"jsonrpc\": \"2.0\", \"method\": \"signin\", \"params\": {\"password\": \"hunter2\"}, \"id\": 3}");
+        logins.handle("{\"jsonrpc\": \"2.0\", \"method\": \"v2.login\", \"params\": {\"password\": \"hunter2\"}, \"id\": 4}");
+        let recorded = lines.take().join("\n");
+        assert!(!recorded.contains("hunter2"));
+        let exchanges = Exchange::read(recorded.as_bytes()).unwrap();
+        assert_eq!(exchanges[0].raw_request(),
+                   "[{\"id\":1,\"jsonrpc\":\"2.0\",\"method\":\"login\",\"params\":{\"password\":\"[REDACTED]\",\"user\":\"bob\"}},\
+                     {\"id\":2,\"jsonrpc\":\"2.0\",\"method\":\"hello\",\"params\":{\"password\":\"kept\"}}]");
+
+        // Clients record what they send and receive, in files too.
+        let path = ::std::env::temp_dir().join(format!("jsonrpc2-record-{}.jsonl", ::std::process::id()));
+        let _ = ::std::fs::remove_file(&path);
+        let mut client = Client::new("http://127.0.0.1:1").unwrap();
+        client.record(Arc::new(Recorder::create(&path).unwrap()));
+        assert!(client.request("add", &Some(vec![1, 2].to_json()), &9).is_err());
+        let exchanges = Exchange::load(&path).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+        assert_eq!(exchanges.len(), 1);
+        assert_eq!(exchanges[0].side(), Side::Client);
+        assert_eq!(exchanges[0].peer(), Some("http://127.0.0.1:1"));
+        assert_eq!(exchanges[0].raw_request(), "{\"id\":9,\"jsonrpc\":\"2.0\",\"method\":\"add\",\"params\":[1,2]}");
+        assert_eq!(exchanges[0].raw_response(), None);
+        assert_eq!(Replay::new(&rpc).run(&exchanges)[0].differences, vec!["$: expected nothing, got {\"id\":9,\"jsonrpc\":\"2.0\",\"result\":3}"]);
+    }
+    #[test]
+    fn test_version_1(){
+        let mut rpc = JsonRpc::new();
+        rpc.register("add", Box::new(add));
+        rpc.set_version(Version::V1);
+        assert_eq!(rpc.handle("{\"method\": \"add\", \"params\": [1, 2], \"id\": 1}").unwrap(),
+                   "{\"error\":null,\"id\":1,\"result\":3}");
+        assert_eq!(rpc.handle("{\"method\": \"add\", \"params\": [1, 2], \"id\": null}"), None);
+        assert_eq!(rpc.handle("{\"method\": \"add\", \"params\": {}, \"id\": 2}").unwrap(),
+                   "{\"error\":{\"code\":-32600,\"data\":null,\"message\":\"Invalid Request\"},\"id\":2,\"result\":null}");
+
+        let req = Request::notification("1.0", "add", None);
+        assert_eq!(req.to_string(), "{\"id\":null,\"method\":\"add\",\"params\":[]}");
+        let res = Response::parse_v1(Json::from_str("{\"result\": null, \"error\": {\"code\": -5, \"message\": \"No such wallet\"}, \"id\": 3}").unwrap()).unwrap();
+        assert_eq!(res.version(), Version::V1);
+        assert_eq!(res.to_string(), "{\"error\":{\"code\":-5,\"data\":null,\"message\":\"No such wallet\"},\"id\":3,\"result\":null}");
+        let res = Response::parse_v1(Json::from_str("{\"result\": 10, \"error\": null, \"id\": 4}").unwrap()).unwrap();
+        assert_eq!(res.to_string(), "{\"error\":null,\"id\":4,\"result\":10}");
+
+        // Clients send notifications in the version they are set to.
+        struct Sent(Mutex<Vec<String>>);
+        impl Transport for Sent {
+            fn send(&self, body: &str) -> Result<Vec<u8>, ClientError> {
+                self.0.lock().unwrap().push(body.to_string());
+                Ok(Vec::new())
+            }
+        }
+        let sent = Arc::new(Sent(Mutex::new(Vec::new())));
+        let mut client = Client::with_transport(Box::new(sent.clone()));
+        assert_eq!(client.notify("add", &Some(vec![1, 2].to_json())), Ok(()));
+        client.set_version(Version::V1);
+        assert_eq!(client.notify("add", &Some(vec![1, 2].to_json())), Ok(()));
+        assert_eq!(*sent.0.lock().unwrap(), vec!["{\"jsonrpc\":\"2.0\",\"method\":\"add\",\"params\":[1,2]}",
+                                                 "{\"id\":null,\"method\":\"add\",\"params\":[1,2]}"]);
+    }
+    #[test]
     fn test_client(){
-        let client = Client::new("http://127.0.0.1").unwrap();
-        let method = "ice";
+        let mock = Mock::new().expect(Expectation::call("ice").params(Params::eq(vec![1,2,3,4])).returns(10).times(2));
+        let client = mock.client();
         let params = vec![1,2,3,4];
-        let id     = 1i64;
-        match client.call("ice", &Some(params.to_json()), &id ) {
-            Ok(result) => println!("Response: {:?}", result),
-            Err(_)     => println!("请求出错啦...")
+        assert_eq!(client.call("ice", &Some(params.to_json()), &1), Ok(Some(10u64.to_json())));
+
+        extern crate hyper;
+        let mut server = mock.serve_http("127.0.0.1:0").unwrap();
+        let client = Client::new(&format!("http://{}", server.socket)).unwrap();
+        let result = client.request("ice", &Some(params.to_json()), &2);
+        let missing = client.request("melt", &None, &3);
+        server.close().unwrap();
+        assert_eq!(result, Ok(Some(10u64.to_json())));
+        assert_eq!(missing, Err(ClientError::Remote(Error::MethodNotFound)));
+        assert_eq!(mock.verify(), Err(vec!["unexpected call: melt".to_string()]));
+
+        // Responses deviating from the spec are accepted unless the client
+        // is strict.
+        struct Deviant;
+        impl Transport for Deviant {
+            fn send(&self, _: &str) -> Result<Vec<u8>, ClientError> {
+                Ok(b"{\"result\": 1, \"id\": 1, \"extra\": true}".to_vec())
+            }
+        }
+        let mut client = Client::with_transport(Box::new(Deviant));
+        assert_eq!(client.request("ice", &None, &1), Ok(Some(1u64.to_json())));
+        client.set_mode(Mode::Strict);
+        assert_eq!(client.request("ice", &None, &1), Err(ClientError::Response(ResponseError::Version(None))));
+    }
+    #[test]
+    fn test_mock(){
+        let mut user = BTreeMap::new();
+        user.insert("user".to_string(), "ann".to_json());
+        let mock = Mock::new()
+            .expect(Expectation::call("login").params(Params::partial(user.clone())).returns("t0ken".to_string()))
+            .expect(Expectation::call("balance").params(Params::Absent).returns(100).at_least(1))
+            .expect(Expectation::call("withdraw").params(Params::check(|params| params.is_some())).fails(Error::from("insufficient funds")))
+            .expect(Expectation::call("logout").at_most(1))
+            .in_order();
+        let client = mock.client();
+        user.insert("password".to_string(), "hunter2".to_json());
+        assert_eq!(client.request("login", &Some(Json::Object(user)), &1), Ok(Some("t0ken".to_json())));
+        assert_eq!(client.request("balance", &None, &2), Ok(Some(100u64.to_json())));
+        assert_eq!(client.request("balance", &None, &3), Ok(Some(100u64.to_json())));
+        assert_eq!(client.request("withdraw", &Some(vec![500].to_json()), &4), Err(ClientError::Remote(Error::from("insufficient funds"))));
+        assert_eq!(mock.verify(), Ok(()));
+        assert_eq!(mock.calls().len(), 4);
+        assert_eq!(mock.calls()[3], ("withdraw".to_string(), Some(vec![500u64].to_json())));
+
+        // A second withdrawal is one too many, and balance takes no params.
+        match client.request("withdraw", &Some(vec![5].to_json()), &5) {
+            Err(ClientError::Remote(Error::InvalidParams(_))) => {},
+            other => panic!("unexpected {:?}", other)
         }
+        match client.request("balance", &Some(vec![1].to_json()), &6) {
+            Err(ClientError::Remote(Error::InvalidParams(Some(data)))) => assert_eq!(data, "no expectation matches balance [1]".to_json()),
+            other => panic!("unexpected {:?}", other)
+        }
+        assert_eq!(mock.verify(), Err(vec!["unexpected call: withdraw [5]".to_string(),
+                                           "unexpected call: balance [1]".to_string()]));
 
+        // Out of order, and short of calls.
+        let mock = Mock::new()
+            .expect(Expectation::call("first"))
+            .expect(Expectation::call("second"))
+            .expect(Expectation::call("third").times(2))
+            .in_order();
+        let rpc = mock.rpc();
+        rpc.handle("{\"jsonrpc\": \"2.0\", \"method\": \"second\", \"id\": 1}");
+        rpc.handle("{\"jsonrpc\": \"2.0\", \"method\": \"first\", \"id\": 2}");
+        rpc.handle("{\"jsonrpc\": \"2.0\", \"method\": \"third\", \"id\": 3}");
+        assert_eq!(mock.verify(), Err(vec!["second was called before first with any params".to_string(),
+                                           "third with any params: expected 2 calls, got 1".to_string()]));
     }
 }
-
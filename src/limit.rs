@@ -0,0 +1,232 @@
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use ::{Request, Response, Context, Error, Json, ToJson};
+use middleware::{Middleware, Next};
+
+/// Code of the errors `ConcurrencyLimit` and `RateLimit` reject calls with
+/// unless told otherwise.
+pub const LIMIT_ERROR_CODE: i64 = -32029;
+
+/// The method serving `request`, or the name it arrived with if none
+/// does, so that aliases and fallbacks share the limits of their target.
+fn served(request: &Request, next: &Next) -> String {
+    let mut route = next.rpc().route(&request.method());
+    route.pop().unwrap_or_default()
+}
+
+fn reject(request: &Request, code: i64, message: &str, retry_after: f64) -> Response {
+    let mut data = BTreeMap::new();
+    data.insert("retry_after".to_string(), retry_after.to_json());
+    Response::Error{
+        jsonrpc: request.jsonrpc(),
+        error  : Error::new(code, message, Some(Json::Object(data))),
+        id     : request.id()
+    }
+}
+
+/// Middleware capping how many calls of each method run at once.
+///
+/// ```ignore
+/// rpc.wrap_prefix("report.", Box::new(ConcurrencyLimit::new(2).queue(Duration::from_secs(5))));
+/// ```
+///
+/// Every method the layer applies to gets its own cap, shared with the
+/// aliases and fallbacks it serves. Calls beyond it are
+/// rejected right away, or wait for a free slot for up to the `queue`
+/// timeout. Rejections carry a `retry_after` in seconds in their `data`.
+pub struct ConcurrencyLimit {
+    max        : usize,
+    queue      : Option<Duration>,
+    code       : i64,
+    retry_after: f64,
+    running    : Mutex<BTreeMap<String, usize>>,
+    freed      : Condvar
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max: usize) -> ConcurrencyLimit {
+        ConcurrencyLimit {
+            max        : max,
+            queue      : None,
+            code       : LIMIT_ERROR_CODE,
+            retry_after: 1.0,
+            running    : Mutex::new(BTreeMap::new()),
+            freed      : Condvar::new()
+        }
+    }
+    /// Makes calls over the cap wait up to `timeout` for a free slot.
+    pub fn queue(mut self, timeout: Duration) -> ConcurrencyLimit {
+        self.queue = Some(timeout);
+        self
+    }
+    /// The code rejections are sent with; `LIMIT_ERROR_CODE` by default.
+    pub fn code(mut self, code: i64) -> ConcurrencyLimit {
+        self.code = code;
+        self
+    }
+    /// The `retry_after` rejections suggest, in seconds; 1 by default.
+    pub fn retry_after(mut self, seconds: f64) -> ConcurrencyLimit {
+        self.retry_after = seconds;
+        self
+    }
+    /// The number of calls served by `method` running now.
+    pub fn running(&self, method: &str) -> usize {
+        self.running.lock().unwrap().get(method).cloned().unwrap_or(0)
+    }
+    fn acquire(&self, method: &str) -> bool {
+        let deadline = self.queue.map(|timeout| Instant::now() + timeout);
+        let mut running = self.running.lock().unwrap();
+        loop {
+            let count = running.get(method).cloned().unwrap_or(0);
+            if count < self.max {
+                running.insert(method.to_string(), count + 1);
+                return true;
+            }
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now < deadline => {
+                    running = self.freed.wait_timeout(running, deadline - now).unwrap().0;
+                },
+                _ => return false
+            }
+        }
+    }
+    fn release(&self, method: &str) {
+        let mut running = self.running.lock().unwrap();
+        let count = running.get(method).cloned().unwrap_or(1);
+        match count {
+            1 => running.remove(method),
+            _ => running.insert(method.to_string(), count - 1)
+        };
+        self.freed.notify_all();
+    }
+}
+
+impl Middleware for ConcurrencyLimit {
+    fn handle(&self, request: Request, ctx: &mut Context, next: Next) -> Response {
+        let method = served(&request, &next);
+        if !self.acquire(&method) {
+            warn!("{} rejected: {} calls are running", method, self.max);
+            return reject(&request, self.code, "Too many concurrent calls", self.retry_after);
+        }
+        // Frees the slot even if the handler unwinds.
+        struct Slot<'a>(&'a ConcurrencyLimit, String);
+        impl<'a> Drop for Slot<'a> {
+            fn drop(&mut self) {
+                self.0.release(&self.1);
+            }
+        }
+        let _slot = Slot(self, method);
+        next.run(request, ctx)
+    }
+}
+
+/// Middleware limiting the rate of calls with token buckets.
+///
+/// ```ignore
+/// // Ten calls a minute per method and caller, in bursts of up to ten.
+/// rpc.wrap(Box::new(RateLimit::new(10, Duration::from_secs(60))));
+/// ```
+///
+/// Each bucket holds up to `capacity` tokens and refills at `capacity`
+/// tokens per `period`; a call takes one token. Buckets are kept per key,
+/// which unless set with `key` is the method serving the call, whatever
+/// alias or fallback it came through, and the caller: the subject of the
+/// `Context` identity if there is one, else the IP address of its peer,
+/// whatever port the caller connects from. Buckets that have refilled are
+/// dropped. Rejections carry the seconds until a token is available as
+/// `retry_after` in their `data`.
+pub struct RateLimit {
+    capacity: f64,
+    rate    : f64,
+    code    : i64,
+    key     : Option<Box<dyn Fn(&Request, &Context) -> String + Send + Sync>>,
+    buckets : Mutex<(BTreeMap<String, (f64, Instant)>, Instant)>
+}
+
+fn caller(ctx: &Context) -> String {
+    match (ctx.identity(), ctx.peer()) {
+        (Some(identity), _) => format!("subject:{}", identity.subject()),
+        (None, Some(peer))  => match peer.parse::<SocketAddr>() {
+            Ok(addr) => addr.ip().to_string(),
+            Err(_)   => peer.to_string()
+        },
+        (None, None)        => String::new()
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+impl RateLimit {
+    pub fn new(capacity: u32, period: Duration) -> RateLimit {
+        RateLimit {
+            capacity: capacity as f64,
+            rate    : capacity as f64 / seconds(period),
+            code    : LIMIT_ERROR_CODE,
+            key     : None,
+            buckets : Mutex::new((BTreeMap::new(), Instant::now()))
+        }
+    }
+    /// The code rejections are sent with; `LIMIT_ERROR_CODE` by default.
+    pub fn code(mut self, code: i64) -> RateLimit {
+        self.code = code;
+        self
+    }
+    /// Sets how calls are grouped into buckets, e.g. by an identity a
+    /// transport stored in the `Context`.
+    pub fn key<F>(mut self, key: F) -> RateLimit
+        where F: Fn(&Request, &Context) -> String + Send + Sync + 'static {
+        self.key = Some(Box::new(key));
+        self
+    }
+    /// The number of buckets kept.
+    pub fn buckets(&self) -> usize {
+        self.buckets.lock().unwrap().0.len()
+    }
+    /// Takes a token from the bucket of `key`, or returns the seconds
+    /// until one is available.
+    fn take(&self, key: String) -> Result<(), f64> {
+        let now = Instant::now();
+        let mut guard = self.buckets.lock().unwrap();
+        let (ref mut buckets, ref mut pruned) = *guard;
+        // A full bucket is no different from a missing one. Looking for them
+        // once per time an empty bucket takes to refill keeps the map to the
+        // callers of about the last period.
+        if seconds(now.duration_since(*pruned)) * self.rate >= self.capacity {
+            let (capacity, rate) = (self.capacity, self.rate);
+            buckets.retain(|_, bucket| bucket.0 + seconds(now.duration_since(bucket.1)) * rate < capacity);
+            *pruned = now;
+        }
+        let bucket = buckets.entry(key).or_insert((self.capacity, now));
+        bucket.0 = (bucket.0 + seconds(now.duration_since(bucket.1)) * self.rate).min(self.capacity);
+        bucket.1 = now;
+        match bucket.0 >= 1.0 {
+            true  => {
+                bucket.0 -= 1.0;
+                Ok(())
+            },
+            false => Err((1.0 - bucket.0) / self.rate)
+        }
+    }
+}
+
+impl Middleware for RateLimit {
+    fn handle(&self, request: Request, ctx: &mut Context, next: Next) -> Response {
+        let key = match self.key {
+            Some(ref key) => key(&request, ctx),
+            None          => served(&request, &next) + " " + &caller(ctx)
+        };
+        match self.take(key.clone()) {
+            Ok(())           => next.run(request, ctx),
+            Err(retry_after) => {
+                warn!("{} rejected: rate limit of {} exceeded", request.method(), key);
+                reject(&request, self.code, "Too many requests", retry_after)
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use ::{JsonRpc, Request, Response, Context, Error, Json};
+
+/// A method handler of a `LocalJsonRpc`, which need not be thread-safe.
+pub type LocalHandle = Box<dyn Fn(&Option<Json>) -> Result<Json, Error>>;
+
+type Handlers = Rc<RefCell<BTreeMap<String, LocalHandle>>>;
+
+thread_local! {
+    // The `LocalJsonRpc`s dispatching on this thread, innermost last.
+    static ACTIVE: RefCell<Vec<(usize, Handlers)>> = RefCell::new(Vec::new());
+}
+
+/// A single-threaded dispatcher for handlers that are not `Send + Sync`,
+/// e.g. ones capturing an `Rc` or a `RefCell`.
+///
+/// ```ignore
+/// let count = Rc::new(Cell::new(0));
+/// let rpc = LocalJsonRpc::new();
+/// rpc.register("count", Box::new(move |_: &Option<Json>| -> RpcResult {
+///     count.set(count.get() + 1);
+///     Ok(count.get().to_json())
+/// }));
+/// ```
+///
+/// A `LocalJsonRpc` can not leave the thread it was created on. Everything
+/// besides local handlers, such as middleware, aliases and descriptions, is
+/// set up on the wrapped `JsonRpc`, which may also hold thread-safe
+/// handlers. Local handlers only run when calls go through the
+/// `LocalJsonRpc`; anywhere else they fail with an `InternalError`.
+pub struct LocalJsonRpc {
+    rpc     : JsonRpc,
+    id      : usize,
+    handlers: Handlers,
+    // Keeps the dispatcher on its thread.
+    local   : PhantomData<Rc<()>>
+}
+
+impl LocalJsonRpc {
+    pub fn new() -> LocalJsonRpc {
+        static IDS: AtomicUsize = AtomicUsize::new(0);
+        LocalJsonRpc {
+            rpc     : JsonRpc::new(),
+            id      : IDS.fetch_add(1, Ordering::SeqCst),
+            handlers: Rc::new(RefCell::new(BTreeMap::new())),
+            local   : PhantomData
+        }
+    }
+    /// Registers a handler that need not be thread-safe; see
+    /// `JsonRpc::register`.
+    pub fn register<F, E>(&self, method: &str, handle: Box<F>)
+        where F: Fn(&Option<Json>) -> Result<Json, E> + ?Sized + 'static, E: Into<Error> {
+        self.handlers.borrow_mut().insert(method.to_string(), Box::new(move |params: &Option<Json>| {
+            handle(params).map_err(Into::into)
+        }));
+        let (id, name) = (self.id, method.to_string());
+        self.rpc.register(method, Box::new(move |params: &Option<Json>| -> Result<Json, Error> {
+            let handlers = ACTIVE.with(|active| {
+                active.borrow().iter().rev().find(|&&(active, _)| active == id).map(|&(_, ref handlers)| handlers.clone())
+            });
+            let handlers = match handlers {
+                Some(handlers) => handlers,
+                None => {
+                    error!("local handler of {} called outside of its LocalJsonRpc", name);
+                    return Err(Error::InternalError(None));
+                }
+            };
+            let handlers = handlers.borrow();
+            match handlers.get(&name) {
+                Some(handle) => handle(params),
+                None         => Err(Error::InternalError(None))
+            }
+        }));
+    }
+    pub fn rpc(&self) -> &JsonRpc {
+        &self.rpc
+    }
+    pub fn rpc_mut(&mut self) -> &mut JsonRpc {
+        &mut self.rpc
+    }
+    /// See `JsonRpc::handle`.
+    pub fn handle(&self, body: &str) -> Option<String> {
+        self.enter(|rpc| rpc.handle(body))
+    }
+    pub fn handle_with(&self, body: &str, ctx: &mut Context) -> Option<String> {
+        self.enter(|rpc| rpc.handle_with(body, ctx))
+    }
+    pub fn call(&self, request: &Request) -> Response {
+        self.enter(|rpc| rpc.call(request))
+    }
+    pub fn call_with(&self, request: &Request, ctx: &mut Context) -> Response {
+        self.enter(|rpc| rpc.call_with(request, ctx))
+    }
+    fn enter<T, F: FnOnce(&JsonRpc) -> T>(&self, f: F) -> T {
+        ACTIVE.with(|active| active.borrow_mut().push((self.id, self.handlers.clone())));
+        // Leaves again even if `f` unwinds.
+        struct Leave;
+        impl Drop for Leave {
+            fn drop(&mut self) {
+                ACTIVE.with(|active| active.borrow_mut().pop());
+            }
+        }
+        let _leave = Leave;
+        f(&self.rpc)
+    }
+}
@@ -0,0 +1,259 @@
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+use ::{JsonRpc, Request, Response, Error, DISCOVER_METHOD};
+
+const DURATION_BUCKETS: &'static [f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+const BYTES_BUCKETS: &'static [f64] = &[64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0];
+const BATCH_BUCKETS: &'static [f64] = &[1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0];
+
+/// Label of calls to methods that are not registered, so that callers
+/// cannot grow the number of series at will.
+pub const UNKNOWN_METHOD: &'static str = "<unknown>";
+
+fn describe(name: &str) -> (&'static str, &'static str) {
+    match name {
+        "jsonrpc_server_requests_total"           => ("counter",   "Requests dispatched."),
+        "jsonrpc_server_errors_total"             => ("counter",   "Error responses sent, by error code."),
+        "jsonrpc_server_in_flight"                => ("gauge",     "Requests being dispatched."),
+        "jsonrpc_server_request_duration_seconds" => ("histogram", "Time spent dispatching requests."),
+        "jsonrpc_server_request_bytes"            => ("histogram", "Size of the requests dispatched."),
+        "jsonrpc_server_response_bytes"           => ("histogram", "Size of the responses sent."),
+        "jsonrpc_server_batch_size"               => ("histogram", "Number of requests in the batches handled."),
+        "jsonrpc_server_panics_total"             => ("counter",   "Handler panics caught."),
+        "jsonrpc_client_requests_total"           => ("counter",   "Calls made."),
+        "jsonrpc_client_errors_total"             => ("counter",   "Error responses received, by error code."),
+        "jsonrpc_client_failures_total"           => ("counter",   "Calls that got no valid response, by reason."),
+        "jsonrpc_client_in_flight"                => ("gauge",     "Calls waiting for a response."),
+        "jsonrpc_client_request_duration_seconds" => ("histogram", "Time spent waiting for responses."),
+        "jsonrpc_client_request_bytes"            => ("histogram", "Size of the requests sent."),
+        "jsonrpc_client_response_bytes"           => ("histogram", "Size of the responses received."),
+        _                                         => ("untyped",   "")
+    }
+}
+
+fn label(name: &str, value: &str) -> String {
+    let value = value.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n");
+    format!("{}=\"{}\"", name, value)
+}
+
+fn seconds(start: Instant) -> f64 {
+    let elapsed = start.elapsed();
+    elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9
+}
+
+struct Histogram {
+    buckets: &'static [f64],
+    counts : Vec<u64>,
+    sum    : f64,
+    count  : u64
+}
+
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Histogram {
+        Histogram { buckets: buckets, counts: vec![0; buckets.len()], sum: 0.0, count: 0 }
+    }
+    fn observe(&mut self, value: f64) {
+        if let Some(i) = self.buckets.iter().position(|bound| value <= *bound) {
+            self.counts[i] += 1;
+        }
+        self.sum   += value;
+        self.count += 1;
+    }
+}
+
+/// Counters, gauges and histograms of the calls served by a `JsonRpc` and
+/// made by a `Client`, rendered in the Prometheus text format.
+///
+/// Available with the `metrics` feature. Every series is labeled with the
+/// method called. A dispatcher and its clients may share one `Metrics`,
+/// see `JsonRpc::set_metrics` and `Client::set_metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    // Keyed by the name and the rendered labels of each series.
+    values    : Mutex<BTreeMap<(&'static str, String), f64>>,
+    histograms: Mutex<BTreeMap<(&'static str, String), Histogram>>
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+    fn add(&self, name: &'static str, labels: String, value: f64) {
+        *self.values.lock().unwrap().entry((name, labels)).or_insert(0.0) += value;
+    }
+    fn observe(&self, name: &'static str, labels: String, buckets: &'static [f64], value: f64) {
+        self.histograms.lock().unwrap()
+                       .entry((name, labels))
+                       .or_insert_with(|| Histogram::new(buckets))
+                       .observe(value);
+    }
+    /// The value of a counter or gauge, e.g.
+    /// `get("jsonrpc_server_requests_total", &[("method", "add")])`.
+    pub fn get(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        let labels = labels.iter().map(|&(name, value)| label(name, value)).collect::<Vec<String>>().join(",");
+        self.values.lock().unwrap()
+                   .iter()
+                   .find(|&(&(ref series, ref rendered), _)| *series == name && *rendered == labels)
+                   .map(|(_, value)| *value)
+    }
+    /// The number of observations and their sum of a histogram.
+    pub fn observed(&self, name: &str, labels: &[(&str, &str)]) -> Option<(u64, f64)> {
+        let labels = labels.iter().map(|&(name, value)| label(name, value)).collect::<Vec<String>>().join(",");
+        self.histograms.lock().unwrap()
+                       .iter()
+                       .find(|&(&(ref series, ref rendered), _)| *series == name && *rendered == labels)
+                       .map(|(_, histogram)| (histogram.count, histogram.sum))
+    }
+    /// Renders every series in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut last = "";
+        let values = self.values.lock().unwrap();
+        for (&(name, ref labels), value) in values.iter() {
+            if name != last {
+                let (kind, help) = describe(name);
+                let _ = write!(out, "# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind);
+                last = name;
+            }
+            match labels.is_empty() {
+                true  => { let _ = write!(out, "{} {}\n", name, value); },
+                false => { let _ = write!(out, "{}{{{}}} {}\n", name, labels, value); }
+            }
+        }
+        let histograms = self.histograms.lock().unwrap();
+        for (&(name, ref labels), histogram) in histograms.iter() {
+            if name != last {
+                let (kind, help) = describe(name);
+                let _ = write!(out, "# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind);
+                last = name;
+            }
+            let prefix = match labels.is_empty() {
+                true  => String::new(),
+                false => labels.clone() + ","
+            };
+            let mut cumulative = 0;
+            for (bound, count) in histogram.buckets.iter().zip(histogram.counts.iter()) {
+                cumulative += *count;
+                let _ = write!(out, "{}_bucket{{{}le=\"{}\"}} {}\n", name, prefix, bound, cumulative);
+            }
+            let _ = write!(out, "{}_bucket{{{}le=\"+Inf\"}} {}\n", name, prefix, histogram.count);
+            match labels.is_empty() {
+                true  => { let _ = write!(out, "{}_sum {}\n{}_count {}\n", name, histogram.sum, name, histogram.count); },
+                false => {
+                    let _ = write!(out, "{}_sum{{{}}} {}\n{}_count{{{}}} {}\n",
+                                   name, labels, histogram.sum, name, labels, histogram.count);
+                }
+            }
+        }
+        out
+    }
+    pub(crate) fn batch(&self, size: usize) {
+        self.observe("jsonrpc_server_batch_size", String::new(), BATCH_BUCKETS, size as f64);
+    }
+    pub(crate) fn panic(&self, method: &str) {
+        self.add("jsonrpc_server_panics_total", label("method", method), 1.0);
+    }
+    /// Starts measuring a call dispatched by a `JsonRpc`.
+    pub(crate) fn serve<'a>(&'a self, method: &str, request: &Request) -> Call<'a> {
+        Call::start(self, &SERVER, method, request)
+    }
+    /// Starts measuring a call made by a `Client`.
+    pub(crate) fn send<'a>(&'a self, method: &str, request: &Request) -> Call<'a> {
+        Call::start(self, &CLIENT, method, request)
+    }
+}
+
+/// The names of the series of one side of a call.
+struct Names {
+    requests : &'static str,
+    errors   : &'static str,
+    in_flight: &'static str,
+    duration : &'static str,
+    request  : &'static str,
+    response : &'static str
+}
+
+const SERVER: Names = Names {
+    requests : "jsonrpc_server_requests_total",
+    errors   : "jsonrpc_server_errors_total",
+    in_flight: "jsonrpc_server_in_flight",
+    duration : "jsonrpc_server_request_duration_seconds",
+    request  : "jsonrpc_server_request_bytes",
+    response : "jsonrpc_server_response_bytes"
+};
+
+const CLIENT: Names = Names {
+    requests : "jsonrpc_client_requests_total",
+    errors   : "jsonrpc_client_errors_total",
+    in_flight: "jsonrpc_client_in_flight",
+    duration : "jsonrpc_client_request_duration_seconds",
+    request  : "jsonrpc_client_request_bytes",
+    response : "jsonrpc_client_response_bytes"
+};
+
+/// One call being measured. The in-flight gauge and the latency are
+/// settled when it is dropped, so that a call which unwinds is counted
+/// too.
+pub(crate) struct Call<'a> {
+    metrics: &'a Metrics,
+    names  : &'static Names,
+    method : String,
+    start  : Instant
+}
+
+impl<'a> Call<'a> {
+    fn start(metrics: &'a Metrics, names: &'static Names, method: &str, request: &Request) -> Call<'a> {
+        let labels = label("method", method);
+        metrics.add(names.requests, labels.clone(), 1.0);
+        metrics.add(names.in_flight, labels.clone(), 1.0);
+        metrics.observe(names.request, labels, BYTES_BUCKETS, request.to_string().len() as f64);
+        Call { metrics: metrics, names: names, method: method.to_string(), start: Instant::now() }
+    }
+    /// Records the response of the call and its size in bytes.
+    pub(crate) fn respond(&self, response: &Response, bytes: usize) {
+        let labels = label("method", &self.method);
+        self.metrics.observe(self.names.response, labels, BYTES_BUCKETS, bytes as f64);
+        if let Response::Error{ ref error, .. } = *response {
+            self.error(error);
+        }
+    }
+    /// Records an error answered to the call.
+    pub(crate) fn error(&self, error: &Error) {
+        let labels = label("method", &self.method) + "," + &label("code", &error.to_i64().to_string());
+        self.metrics.add(self.names.errors, labels, 1.0);
+    }
+    /// Records a call that got no valid response; see `ClientError`.
+    pub(crate) fn fail(&self, reason: &str) {
+        let labels = label("method", &self.method) + "," + &label("reason", reason);
+        self.metrics.add("jsonrpc_client_failures_total", labels, 1.0);
+    }
+}
+
+impl<'a> Drop for Call<'a> {
+    fn drop(&mut self) {
+        let labels = label("method", &self.method);
+        self.metrics.add(self.names.in_flight, labels.clone(), -1.0);
+        self.metrics.observe(self.names.duration, labels, DURATION_BUCKETS, seconds(self.start));
+    }
+}
+
+impl JsonRpc {
+    /// The metrics of the calls dispatched; see `Metrics`.
+    pub fn metrics(&self) -> &::std::sync::Arc<Metrics> {
+        &self.metrics
+    }
+    /// Records into `metrics` instead, e.g. to share them with clients.
+    pub fn set_metrics(&mut self, metrics: ::std::sync::Arc<Metrics>) {
+        self.metrics = metrics;
+    }
+    /// The method label of calls to `method`.
+    pub(crate) fn metric_label<'a>(&self, method: &'a str) -> &'a str {
+        match method == DISCOVER_METHOD || self.resolve(method).is_some() {
+            true  => method,
+            false => UNKNOWN_METHOD
+        }
+    }
+}
@@ -0,0 +1,79 @@
+
+use ::{JsonRpc, Request, Response, Context};
+
+/// A layer around `JsonRpc` dispatch.
+///
+/// A middleware receives the request, the call context and the rest of the
+/// chain. It may rewrite the request before passing it on with `next.run`,
+/// answer on its own without calling `next` at all, or inspect and modify
+/// the response that comes back.
+///
+/// Middleware is shared by every thread serving the `JsonRpc`, so it must be
+/// `Send + Sync`.
+pub trait Middleware: Send + Sync {
+    fn handle(&self, request: Request, ctx: &mut Context, next: Next) -> Response;
+}
+
+impl<F> Middleware for F where F: Fn(Request, &mut Context, Next) -> Response + Send + Sync {
+    fn handle(&self, request: Request, ctx: &mut Context, next: Next) -> Response {
+        self(request, ctx, next)
+    }
+}
+
+pub struct Layer {
+    prefix    : Option<String>,
+    middleware: Box<dyn Middleware>
+}
+
+impl Layer {
+    pub fn new(prefix: Option<&str>, middleware: Box<dyn Middleware>) -> Layer {
+        Layer {
+            prefix    : prefix.map(|prefix| prefix.to_string()),
+            middleware: middleware
+        }
+    }
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_ref().map(|prefix| prefix.as_ref())
+    }
+    /// Re-scopes a layer of a router that is being mounted under
+    /// `namespace`, so it keeps applying to the same methods.
+    pub fn nest(self, namespace: &str) -> Layer {
+        let prefix = match self.prefix {
+            Some(prefix) => namespace.to_string() + prefix.as_ref(),
+            None         => namespace.to_string()
+        };
+        Layer { prefix: Some(prefix), middleware: self.middleware }
+    }
+    /// Global layers match every method, scoped ones only the methods
+    /// starting with their prefix.
+    pub fn matches(&self, method: &str) -> bool {
+        match self.prefix {
+            Some(ref prefix) => method.starts_with(prefix.as_str()),
+            None             => true
+        }
+    }
+}
+
+/// The remainder of the middleware chain, ending at the method handler.
+pub struct Next<'a> {
+    rpc   : &'a JsonRpc,
+    layers: &'a [&'a Layer]
+}
+
+impl<'a> Next<'a> {
+    pub fn new(rpc: &'a JsonRpc, layers: &'a [&'a Layer]) -> Next<'a> {
+        Next { rpc: rpc, layers: layers }
+    }
+    /// The `JsonRpc` the call is dispatched by.
+    pub fn rpc(&self) -> &'a JsonRpc {
+        self.rpc
+    }
+    pub fn run(self, request: Request, ctx: &mut Context) -> Response {
+        match self.layers.split_first() {
+            Some((layer, rest)) => {
+                layer.middleware.handle(request, ctx, Next::new(self.rpc, rest))
+            },
+            None => self.rpc.dispatch(&request, ctx)
+        }
+    }
+}
@@ -0,0 +1,279 @@
+extern crate hyper;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use self::hyper::server::{Server, Listening};
+use ::{JsonRpc, Request, Response, Context, Client, Error, Json, ToJson, HttpHandler};
+use middleware::{Middleware, Next};
+
+/// Whether two JSON values are equal, taking integers that parsed as
+/// unsigned to be the same as their signed equivalents. With `partial`,
+/// `actual` may have object members `expected` does not mention.
+fn same(expected: &Json, actual: &Json, partial: bool) -> bool {
+    match (expected, actual) {
+        (&Json::Object(ref expected), &Json::Object(ref actual)) => {
+            (partial || expected.len() == actual.len())
+                && expected.iter().all(|(key, value)| actual.get(key).map_or(false, |actual| same(value, actual, partial)))
+        },
+        (&Json::Array(ref expected), &Json::Array(ref actual)) => {
+            expected.len() == actual.len() && expected.iter().zip(actual).all(|(expected, actual)| same(expected, actual, partial))
+        },
+        (expected, actual) if expected.is_number() && actual.is_number() => expected.as_f64() == actual.as_f64(),
+        (expected, actual) => expected == actual
+    }
+}
+
+/// What the params of an expected call must look like.
+pub enum Params {
+    /// Anything, or nothing.
+    Any,
+    /// No params at all.
+    Absent,
+    /// Exactly these params.
+    Eq(Json),
+    /// Params with at least these members, at any depth, and arrays of the
+    /// same length matching element by element.
+    Partial(Json),
+    /// Params `check` returns `true` for.
+    Check(Box<dyn Fn(&Option<Json>) -> bool + Send + Sync>)
+}
+
+impl Params {
+    pub fn eq<T: ToJson>(params: T) -> Params {
+        Params::Eq(params.to_json())
+    }
+    pub fn partial<T: ToJson>(params: T) -> Params {
+        Params::Partial(params.to_json())
+    }
+    pub fn check<F>(check: F) -> Params where F: Fn(&Option<Json>) -> bool + Send + Sync + 'static {
+        Params::Check(Box::new(check))
+    }
+    pub fn matches(&self, params: &Option<Json>) -> bool {
+        match (self, params) {
+            (&Params::Any, _)                                   => true,
+            (&Params::Absent, &None)                            => true,
+            (&Params::Eq(ref expected), &Some(ref actual))      => same(expected, actual, false),
+            (&Params::Partial(ref expected), &Some(ref actual)) => same(expected, actual, true),
+            (&Params::Check(ref check), params)                 => check(params),
+            _                                                   => false
+        }
+    }
+}
+
+impl fmt::Display for Params {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Params::Any                 => write!(f, "any params"),
+            Params::Absent              => write!(f, "no params"),
+            Params::Eq(ref params)      => write!(f, "params {}", params),
+            Params::Partial(ref params) => write!(f, "params like {}", params),
+            Params::Check(_)            => write!(f, "params passing a check")
+        }
+    }
+}
+
+/// A call a `Mock` expects, and what it answers.
+///
+/// By default the call is expected exactly once, with any params, and
+/// answered with a `null` result.
+pub struct Expectation {
+    method: String,
+    params: Params,
+    answer: Result<Json, Error>,
+    min   : usize,
+    max   : Option<usize>,
+    calls : usize
+}
+
+impl Expectation {
+    pub fn call(method: &str) -> Expectation {
+        Expectation {
+            method: method.to_string(),
+            params: Params::Any,
+            answer: Ok(Json::Null),
+            min   : 1,
+            max   : Some(1),
+            calls : 0
+        }
+    }
+    pub fn params(mut self, params: Params) -> Expectation {
+        self.params = params;
+        self
+    }
+    pub fn returns<T: ToJson>(mut self, result: T) -> Expectation {
+        self.answer = Ok(result.to_json());
+        self
+    }
+    pub fn fails(mut self, error: Error) -> Expectation {
+        self.answer = Err(error);
+        self
+    }
+    /// Expects exactly `n` calls; `times(0)` forbids the call.
+    pub fn times(mut self, n: usize) -> Expectation {
+        self.min = n;
+        self.max = Some(n);
+        self
+    }
+    pub fn at_least(mut self, n: usize) -> Expectation {
+        self.min = n;
+        self.max = None;
+        self
+    }
+    pub fn at_most(mut self, n: usize) -> Expectation {
+        self.min = 0;
+        self.max = Some(n);
+        self
+    }
+    fn matches(&self, method: &str, params: &Option<Json>) -> bool {
+        self.method == method && self.params.matches(params)
+    }
+    fn saturated(&self) -> bool {
+        self.max.map_or(false, |max| self.calls >= max)
+    }
+    fn satisfied(&self) -> bool {
+        self.calls >= self.min
+    }
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} with {}", self.method, self.params)
+    }
+}
+
+fn describe(method: &str, params: &Option<Json>) -> String {
+    match *params {
+        Some(ref params) => format!("{} {}", method, params),
+        None             => method.to_string()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    expectations: Vec<Expectation>,
+    ordered     : bool,
+    calls       : Vec<(String, Option<Json>)>,
+    problems    : Vec<String>
+}
+
+impl State {
+    fn answer(&mut self, method: &str, params: &Option<Json>) -> Result<Json, Error> {
+        self.calls.push((method.to_string(), params.clone()));
+        let found = self.expectations.iter().position(|expectation| {
+            expectation.matches(method, params) && !expectation.saturated()
+        });
+        let index = match found {
+            Some(index) => index,
+            None => {
+                let call = describe(method, params);
+                self.problems.push(format!("unexpected call: {}", call));
+                return match self.expectations.iter().any(|expectation| expectation.method == method) {
+                    true  => Err(Error::InvalidParams(Some(format!("no expectation matches {}", call).to_json()))),
+                    false => Err(Error::MethodNotFound)
+                };
+            }
+        };
+        if self.ordered {
+            let pending = self.expectations[.. index].iter().find(|expectation| !expectation.satisfied());
+            if let Some(pending) = pending {
+                let problem = format!("{} was called before {}", describe(method, params), pending);
+                self.problems.push(problem);
+            }
+        }
+        self.expectations[index].calls += 1;
+        self.expectations[index].answer.clone()
+    }
+}
+
+/// A stand-in for a JSON-RPC service, answering expected calls with canned
+/// results or errors, for testing code that uses a `Client`.
+///
+/// ```ignore
+/// let mock = Mock::new()
+///     .expect(Expectation::call("login").params(Params::partial(user)).returns("t0ken".to_string()))
+///     .expect(Expectation::call("balance").returns(100).at_least(1))
+///     .in_order();
+/// let client = mock.client();
+/// // ... exercise the code under test with `client` ...
+/// mock.assert();
+/// ```
+///
+/// Each call is answered by the first expectation it matches that has not
+/// had all its calls yet. Calls matching none are answered with
+/// `MethodNotFound`, or `InvalidParams` if the method is expected with
+/// other params. Unexpected calls, expectations called too few times and,
+/// with `in_order`, calls made before earlier expectations were met are
+/// reported by `verify`.
+///
+/// Clones share their expectations and calls.
+#[derive(Clone, Default)]
+pub struct Mock {
+    state: Arc<Mutex<State>>
+}
+
+impl Mock {
+    pub fn new() -> Mock {
+        Mock::default()
+    }
+    pub fn expect(self, expectation: Expectation) -> Mock {
+        self.state.lock().unwrap().expectations.push(expectation);
+        self
+    }
+    /// Expects the calls in the order they were declared.
+    pub fn in_order(self) -> Mock {
+        self.state.lock().unwrap().ordered = true;
+        self
+    }
+    /// A dispatcher answering as the mock.
+    pub fn rpc(&self) -> JsonRpc {
+        let mut rpc = JsonRpc::new();
+        rpc.wrap(Box::new(self.clone()));
+        rpc
+    }
+    /// A client calling the mock in memory.
+    pub fn client(&self) -> Client {
+        Client::with_transport(Box::new(self.rpc()))
+    }
+    /// Serves the mock over HTTP at `addr`, e.g. `127.0.0.1:0` for any free
+    /// port; clients can reach it at `http://` and the `socket` of the
+    /// returned server, which stops when it is closed.
+    pub fn serve_http(&self, addr: &str) -> Result<Listening, hyper::Error> {
+        Server::http(addr)?.handle(HttpHandler::new(Arc::new(self.rpc())))
+    }
+    /// Every call made so far, in order, with its method and params.
+    pub fn calls(&self) -> Vec<(String, Option<Json>)> {
+        self.state.lock().unwrap().calls.clone()
+    }
+    /// Checks that every expectation was met and nothing else was called.
+    pub fn verify(&self) -> Result<(), Vec<String>> {
+        let state = self.state.lock().unwrap();
+        let mut problems = state.problems.clone();
+        for expectation in state.expectations.iter().filter(|expectation| !expectation.satisfied()) {
+            let expected = match expectation.max {
+                Some(max) if max == expectation.min => format!("{}", max),
+                _                                   => format!("at least {}", expectation.min)
+            };
+            problems.push(format!("{}: expected {} calls, got {}", expectation, expected, expectation.calls));
+        }
+        match problems.is_empty() {
+            true  => Ok(()),
+            false => Err(problems)
+        }
+    }
+    /// Panics with the problems `verify` finds, if any.
+    pub fn assert(&self) {
+        if let Err(problems) = self.verify() {
+            panic!("mock expectations not met:\n  {}", problems.join("\n  "));
+        }
+    }
+}
+
+impl Middleware for Mock {
+    fn handle(&self, request: Request, _: &mut Context, _: Next) -> Response {
+        let answer = self.state.lock().unwrap().answer(&request.method(), &request.params());
+        match answer {
+            Ok(result) => Response::Success{ jsonrpc: request.jsonrpc(), result: Some(result), id: request.id() },
+            Err(error) => Response::Error{ jsonrpc: request.jsonrpc(), error: error, id: request.id() }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+
+use std::fmt;
+use ::{Json, ToJson, Object};
+
+/// How closely incoming messages must follow the JSON-RPC 2.0 spec;
+/// `Strict` by default. `JsonRpc` servers parse leniently unless told
+/// otherwise, and log each deviation they accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Only messages that follow the spec to the letter are accepted.
+    Strict,
+    /// Common deviations from the spec are tolerated, e.g. `"params": null`.
+    Lenient
+}
+
+impl Default for Mode {
+    fn default() -> Mode {
+        Mode::Strict
+    }
+}
+
+/// Protocol version of a message.
+///
+/// `Request` and `Response` keep it in their `jsonrpc` string, so a message
+/// built or parsed with `"1.0"` there is written in the 1.0 format: without
+/// a `jsonrpc` member, with array `params`, with both `result` and `error`
+/// in responses, and with `"id": null` for notifications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V1,
+    V2
+}
+
+impl Version {
+    /// The `jsonrpc` string of messages of this version.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Version::V1 => "1.0",
+            Version::V2 => "2.0"
+        }
+    }
+    /// The version of a message with the given `jsonrpc` string.
+    pub fn of(jsonrpc: &str) -> Version {
+        match jsonrpc {
+            "1.0" => Version::V1,
+            _     => Version::V2
+        }
+    }
+}
+
+impl Default for Version {
+    fn default() -> Version {
+        Version::V2
+    }
+}
+
+/// The `id` of a request, echoed in its response: an integer or a string,
+/// as sent. A null id is `None` where ids are optional.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Id {
+    Number(i64),
+    String(String)
+}
+
+impl From<i64> for Id {
+    fn from(id: i64) -> Id {
+        Id::Number(id)
+    }
+}
+
+impl<'a> From<&'a str> for Id {
+    fn from(id: &'a str) -> Id {
+        Id::String(id.to_string())
+    }
+}
+
+impl From<String> for Id {
+    fn from(id: String) -> Id {
+        Id::String(id)
+    }
+}
+
+impl ToJson for Id {
+    fn to_json(&self) -> Json {
+        match *self {
+            Id::Number(id)     => id.to_json(),
+            Id::String(ref id) => id.to_json()
+        }
+    }
+}
+
+/// The number, or the string without quotes.
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Id::Number(id)     => write!(f, "{}", id),
+            Id::String(ref id) => write!(f, "{}", id)
+        }
+    }
+}
+
+/// A departure from the spec that `Mode::Lenient` tolerated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Deviation {
+    /// The `jsonrpc` member was missing.
+    MissingVersion,
+    /// `jsonrpc` was not the string `"2.0"` but close enough, e.g. `"2"`.
+    Version(Json),
+    /// `params` was `null` instead of being omitted.
+    NullParams,
+    /// A response had `result` or `error` set to `null` next to the other.
+    NullMember(String),
+    /// A top-level member the spec does not define.
+    UnknownMember(String)
+}
+
+impl fmt::Display for Deviation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Deviation::MissingVersion       => write!(f, "missing \"jsonrpc\" member"),
+            Deviation::Version(ref version) => write!(f, "\"jsonrpc\" is {} instead of \"2.0\"", version),
+            Deviation::NullParams           => write!(f, "\"params\" is null"),
+            Deviation::NullMember(ref m)    => write!(f, "\"{}\" is null", m),
+            Deviation::UnknownMember(ref m) => write!(f, "unknown member \"{}\"", m)
+        }
+    }
+}
+
+/// Reads the `jsonrpc` member, which must be `"2.0"` unless `mode` is
+/// lenient, in which case a missing member or `"2"`/`2`/`2.0` is accepted.
+pub fn parse_version(obj: &Object, mode: Mode, deviations: &mut Vec<Deviation>) -> Result<String, ()> {
+    match obj.get("jsonrpc") {
+        Some(&Json::String(ref version)) if version == "2.0" => return Ok(version.clone()),
+        _ if mode == Mode::Strict => return Err(()),
+        None => deviations.push(Deviation::MissingVersion),
+        Some(&Json::String(ref version)) if version == "2" => {
+            deviations.push(Deviation::Version(Json::String(version.clone())));
+        },
+        Some(version) if version.as_f64() == Some(2.0) => {
+            deviations.push(Deviation::Version(version.clone()));
+        },
+        Some(_) => return Err(())
+    }
+    Ok("2.0".to_string())
+}
+
+/// Reads the `id` member, which must be present and an integer, a string
+/// or null; null is `None`. Strings are kept as they are, even when they
+/// hold a number.
+pub fn parse_id(obj: &Object) -> Result<Option<Id>, ()> {
+    match obj.get("id") {
+        // Unsigned ids beyond `i64::MAX` have no `as_i64`.
+        Some(id) if id.is_i64() || id.is_u64() => id.as_i64().map(|id| Some(Id::Number(id))).ok_or(()),
+        Some(&Json::String(ref id)) => Ok(Some(Id::String(id.clone()))),
+        Some(&Json::Null) => Ok(None),
+        _ => Err(())
+    }
+}
+
+/// Checks that `obj` has no members besides `known`; in lenient mode the
+/// others are only reported. Fails with the first unknown member.
+pub fn check_members(obj: &Object, known: &[&str], mode: Mode, deviations: &mut Vec<Deviation>) -> Result<(), String> {
+    for member in obj.keys().filter(|member| !known.contains(&member.as_str())) {
+        match mode {
+            Mode::Strict  => return Err(member.clone()),
+            Mode::Lenient => deviations.push(Deviation::UnknownMember(member.clone()))
+        }
+    }
+    Ok(())
+}
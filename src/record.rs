@@ -0,0 +1,394 @@
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use ::{JsonRpc, Request, Response, Context, Error, Json, ToJson};
+use access_log::{timestamp, redact_params};
+
+/// Which end of a call an exchange was recorded at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    /// Handled by a `JsonRpc`.
+    Server,
+    /// Sent by a `Client`.
+    Client
+}
+
+impl Side {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Side::Server => "server",
+            Side::Client => "client"
+        }
+    }
+}
+
+/// One request body and the response body it got, as recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exchange {
+    time    : String,
+    duration: f64,
+    side    : Side,
+    peer    : Option<String>,
+    request : String,
+    response: Option<String>
+}
+
+/// Parses `body`, keeping it as a string if it is not JSON.
+fn parsed(body: &str) -> Json {
+    Json::from_str(body).unwrap_or(Json::String(body.to_string()))
+}
+
+impl Exchange {
+    pub fn new(side: Side, peer: Option<&str>, time: SystemTime, duration: Duration,
+               request: &str, response: Option<&str>) -> Exchange {
+        Exchange {
+            time    : timestamp(time),
+            duration: duration.as_secs() as f64 * 1e3 + duration.subsec_nanos() as f64 / 1e6,
+            side    : side,
+            peer    : peer.map(|peer| peer.to_string()),
+            request : request.to_string(),
+            response: response.map(|response| response.to_string())
+        }
+    }
+    /// When the request was received or sent, in RFC 3339.
+    pub fn time(&self) -> &str {
+        &self.time
+    }
+    /// How long the response took, in milliseconds.
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+    pub fn side(&self) -> Side {
+        self.side
+    }
+    /// The peer of a server exchange, the URL of a client one.
+    pub fn peer(&self) -> Option<&str> {
+        self.peer.as_ref().map(|peer| peer.as_ref())
+    }
+    /// The request body, byte for byte.
+    pub fn raw_request(&self) -> &str {
+        &self.request
+    }
+    /// The response body, or `None` if there was none: the request held
+    /// only notifications, or a client got no answer.
+    pub fn raw_response(&self) -> Option<&str> {
+        self.response.as_ref().map(|response| response.as_ref())
+    }
+    /// The requests of the body, one unless it is a batch.
+    pub fn requests(&self) -> Vec<Result<Request, Error>> {
+        match Json::from_str(&self.request) {
+            Ok(Json::Array(batch)) => batch.into_iter().map(Request::parse).collect(),
+            Ok(json)               => vec![Request::parse(json)],
+            Err(_)                 => vec![Err(Error::ParseError)]
+        }
+    }
+    /// The responses of the body that could be parsed.
+    pub fn responses(&self) -> Vec<Response> {
+        let json = match self.response.as_ref().map(|response| Json::from_str(response)) {
+            Some(Ok(json)) => json,
+            _              => return Vec::new()
+        };
+        let responses = match json {
+            Json::Array(batch) => batch,
+            json               => vec![json]
+        };
+        responses.into_iter().filter_map(|json| Response::parse(json).ok()).collect()
+    }
+    /// Reads the exchanges written by a `Recorder`, skipping lines that are
+    /// not exchanges.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Vec<Exchange>> {
+        let mut exchanges = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            match Json::from_str(&line).ok().and_then(|json| Exchange::from_json(&json)) {
+                Some(exchange) => exchanges.push(exchange),
+                None if line.trim().is_empty() => {},
+                None => warn!("skipping a line that is not a recorded exchange: {}", line)
+            }
+        }
+        Ok(exchanges)
+    }
+    /// Reads the exchanges recorded in the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<Exchange>> {
+        Exchange::read(BufReader::new(File::open(path)?))
+    }
+    /// Reads an exchange written by a `Recorder`.
+    pub fn from_json(json: &Json) -> Option<Exchange> {
+        let side = match json.find("side").and_then(|side| side.as_string()) {
+            Some("server") => Side::Server,
+            Some("client") => Side::Client,
+            _              => return None
+        };
+        Some(Exchange {
+            time    : json.find("ts").and_then(|ts| ts.as_string()).unwrap_or("").to_string(),
+            duration: json.find("duration_ms").and_then(|duration| duration.as_f64()).unwrap_or(0.0),
+            side    : side,
+            peer    : json.find("peer").and_then(|peer| peer.as_string()).map(|peer| peer.to_string()),
+            request : json.find("request_raw").and_then(|request| request.as_string())?.to_string(),
+            response: json.find("response_raw").and_then(|response| response.as_string()).map(|response| response.to_string())
+        })
+    }
+}
+
+/// The raw bodies go with their parsed form, which is `null` for bodies
+/// that are not JSON.
+impl ToJson for Exchange {
+    fn to_json(&self) -> Json {
+        let mut json = BTreeMap::new();
+        json.insert("ts".to_string(),           self.time.to_json());
+        json.insert("duration_ms".to_string(),  self.duration.to_json());
+        json.insert("side".to_string(),         self.side.as_str().to_json());
+        json.insert("peer".to_string(),         self.peer.to_json());
+        json.insert("request_raw".to_string(),  self.request.to_json());
+        json.insert("request".to_string(),      Json::from_str(&self.request).unwrap_or(Json::Null));
+        json.insert("response_raw".to_string(), self.response.to_json());
+        json.insert("response".to_string(),     self.response.as_ref()
+                                                              .and_then(|response| Json::from_str(response).ok())
+                                                              .unwrap_or(Json::Null));
+        Json::Object(json)
+    }
+}
+
+/// Writes the traffic of a `JsonRpc` or a `Client` as JSON lines, one
+/// `Exchange` per line.
+///
+/// ```ignore
+/// let recorder = Arc::new(Recorder::create("traffic.jsonl")?);
+/// rpc.record(recorder.clone());
+/// client.record(recorder);
+/// ```
+///
+/// Only bodies are recorded, so headers stay out of the file, but bodies
+/// are recorded as they are: params such as passwords have to be left out
+/// with `redact`, and results such as session tokens are written too.
+/// Bodies that are not UTF-8 are recorded with their invalid bytes
+/// replaced.
+pub struct Recorder {
+    writer: Mutex<Box<dyn Write + Send>>,
+    redact: Vec<(String, String)>
+}
+
+impl Recorder {
+    pub fn new(writer: Box<dyn Write + Send>) -> Recorder {
+        Recorder { writer: Mutex::new(writer), redact: Vec::new() }
+    }
+    /// Appends to the file at `path`, creating it if need be.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Recorder> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder::new(Box::new(file)))
+    }
+    /// Redacts the params at `path` of `method`, or of every method if
+    /// `method` is `*`, as `AccessLog::redact` does: calls a `JsonRpc`
+    /// serves through an alias or a fallback of `method` are redacted too.
+    /// A request body with redacted params is recorded re-serialized rather
+    /// than byte for byte.
+    pub fn redact(mut self, method: &str, path: &str) -> Recorder {
+        self.redact.push((method.to_string(), path.to_string()));
+        self
+    }
+    /// Records `exchange`, matching redactions against the methods named
+    /// in the request.
+    pub fn record(&self, exchange: &Exchange) {
+        self.write(exchange, &|method| vec![method.to_string()]);
+    }
+    /// Records an exchange served by `rpc`, matching redactions against
+    /// every name the calls go through.
+    pub(crate) fn record_served(&self, exchange: &Exchange, rpc: &JsonRpc) {
+        self.write(exchange, &|method| rpc.route(method));
+    }
+    fn write(&self, exchange: &Exchange, route: &dyn Fn(&str) -> Vec<String>) {
+        let json = match self.redacted(&exchange.request, route) {
+            Some(request) => Exchange { request: request, ..exchange.clone() }.to_json(),
+            None          => exchange.to_json()
+        };
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(err) = writeln!(writer, "{}", json).and_then(|_| writer.flush()) {
+            warn!("could not record the exchange: {}", err);
+        }
+    }
+    /// The request body with the redactions applied, or `None` if none
+    /// applies to it.
+    fn redacted(&self, body: &str, route: &dyn Fn(&str) -> Vec<String>) -> Option<String> {
+        if self.redact.is_empty() {
+            return None;
+        }
+        let mut json = match Json::from_str(body) {
+            Ok(json) => json,
+            Err(_)   => return None
+        };
+        let original = json.clone();
+        {
+            let requests = match json {
+                Json::Array(ref mut batch) => batch.iter_mut().collect::<Vec<&mut Json>>(),
+                ref mut json               => vec![json]
+            };
+            for request in requests {
+                if let Json::Object(ref mut request) = *request {
+                    let names = match request.get("method").and_then(|method| method.as_string()) {
+                        Some(method) => route(method),
+                        None         => continue
+                    };
+                    if let Some(params) = request.get_mut("params") {
+                        redact_params(params, &names, &self.redact);
+                    }
+                }
+            }
+        }
+        match json == original {
+            true  => None,
+            false => Some(json.to_string())
+        }
+    }
+}
+
+/// Splits a dotted path, as taken by `Replay::ignore`.
+fn segments(path: &str) -> Vec<String> {
+    path.split('.').map(|segment| segment.to_string()).collect()
+}
+
+fn matches(pattern: &[String], path: &[String]) -> bool {
+    pattern.len() == path.len() && pattern.iter().zip(path).all(|(expected, segment)| expected == "*" || expected == segment)
+}
+
+/// Lists where `actual` differs from `expected`, as `path: expected X, got
+/// Y` lines, leaving out the paths matching `ignored`.
+fn diff(expected: &Json, actual: &Json, path: &mut Vec<String>, ignored: &[Vec<String>], out: &mut Vec<String>) {
+    if ignored.iter().any(|pattern| matches(pattern, path)) {
+        return;
+    }
+    let name = |path: &[String]| match path.is_empty() {
+        true  => "$".to_string(),
+        false => path.join(".")
+    };
+    match (expected, actual) {
+        (&Json::Object(ref expected), &Json::Object(ref actual)) => {
+            for key in expected.keys().chain(actual.keys().filter(|key| !expected.contains_key(*key))) {
+                path.push(key.clone());
+                match (expected.get(key), actual.get(key)) {
+                    (Some(expected), Some(actual)) => diff(expected, actual, path, ignored, out),
+                    _ if ignored.iter().any(|pattern| matches(pattern, path)) => {},
+                    (Some(expected), None) => out.push(format!("{}: expected {}, got nothing", name(path), expected)),
+                    (None, Some(actual))   => out.push(format!("{}: expected nothing, got {}", name(path), actual)),
+                    (None, None)           => {}
+                }
+                path.pop();
+            }
+        },
+        (&Json::Array(ref expected), &Json::Array(ref actual)) if expected.len() == actual.len() => {
+            for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                path.push(i.to_string());
+                diff(expected, actual, path, ignored, out);
+                path.pop();
+            }
+        },
+        // Integers parse as I64 or U64 depending on their sign.
+        (expected, actual) if expected == actual || (expected.is_number() && expected.as_f64() == actual.as_f64()) => {},
+        (expected, actual) => out.push(format!("{}: expected {}, got {}", name(path), expected, actual))
+    }
+}
+
+/// An exchange whose replayed response differs from the recorded one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// The position of the exchange in the recording.
+    pub index      : usize,
+    pub request    : String,
+    pub expected   : Option<String>,
+    pub actual     : Option<String>,
+    /// Where the responses differ, one `path: expected X, got Y` per
+    /// difference.
+    pub differences: Vec<String>
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "exchange {}: {}", self.index, self.request)?;
+        for difference in &self.differences {
+            write!(f, "\n  {}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replays recorded requests against a `JsonRpc` and compares its
+/// responses with the recorded ones.
+///
+/// ```ignore
+/// let exchanges = Exchange::load("traffic.jsonl")?;
+/// let mismatches = Replay::new(&rpc).ignore("error.data.correlation_id").run(&exchanges);
+/// for mismatch in &mismatches {
+///     println!("{}", mismatch);
+/// }
+/// ```
+///
+/// Responses are compared as JSON, so key order and whitespace do not
+/// matter. Values that differ from run to run, such as generated ids, can
+/// be left out with `ignore`.
+pub struct Replay<'a> {
+    rpc    : &'a JsonRpc,
+    ignored: Vec<Vec<String>>
+}
+
+impl<'a> Replay<'a> {
+    pub fn new(rpc: &'a JsonRpc) -> Replay<'a> {
+        Replay { rpc: rpc, ignored: Vec::new() }
+    }
+    /// Leaves the values at `path` out of the comparison. The path is a
+    /// dotted list of member names and array indices, where `*` stands for
+    /// any one of them, e.g. `result.created_at` or `*.error.data` for
+    /// every response of a batch.
+    pub fn ignore(mut self, path: &str) -> Replay<'a> {
+        self.ignored.push(segments(path));
+        self
+    }
+    /// Replays `exchange`, with the recorded peer in the call `Context`.
+    /// Replayed calls are not recorded, even if the `JsonRpc` records its
+    /// traffic.
+    pub fn replay(&self, exchange: &Exchange) -> Option<String> {
+        let mut ctx = match exchange.peer {
+            Some(ref peer) if exchange.side == Side::Server => Context::with_peer(peer),
+            _                                               => Context::new()
+        };
+        self.rpc.handle_body(&exchange.request, &mut ctx)
+    }
+    /// Replays every exchange in order and returns those whose responses
+    /// differ.
+    pub fn run(&self, exchanges: &[Exchange]) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        for (index, exchange) in exchanges.iter().enumerate() {
+            let actual = self.replay(exchange);
+            let mut differences = Vec::new();
+            match (exchange.response.as_ref(), actual.as_ref()) {
+                (Some(expected), Some(actual)) => {
+                    diff(&parsed(expected), &parsed(actual), &mut Vec::new(), &self.ignored, &mut differences);
+                },
+                (Some(expected), None) => differences.push(format!("$: expected {}, got nothing", expected)),
+                (None, Some(actual))   => differences.push(format!("$: expected nothing, got {}", actual)),
+                (None, None)           => {}
+            }
+            if !differences.is_empty() {
+                mismatches.push(Mismatch {
+                    index      : index,
+                    request    : exchange.request.clone(),
+                    expected   : exchange.response.clone(),
+                    actual     : actual,
+                    differences: differences
+                });
+            }
+        }
+        mismatches
+    }
+}
+
+impl JsonRpc {
+    /// Records every body handled, and the response to it, with
+    /// `recorder`; see `Recorder`.
+    pub fn record(&mut self, recorder: Arc<Recorder>) {
+        self.recorder = Some(recorder);
+    }
+}
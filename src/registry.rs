@@ -0,0 +1,57 @@
+
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver};
+use ::{JsonRpc, Error, Json, into_handle};
+
+/// A change to the methods of a `JsonRpc`, as seen through `watch`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Registered(String),
+    Replaced(String),
+    Unregistered(String)
+}
+
+impl JsonRpc {
+    /// Removes `method` along with its description; returns whether it was
+    /// registered.
+    ///
+    /// Calls already running keep the handler until they return, and calls
+    /// arriving afterwards get `MethodNotFound`, or are served through an
+    /// alias or fallback that still resolves. A handler registered later
+    /// under the same name starts without declared params.
+    pub fn unregister(&self, method: &str) -> bool {
+        let removed = {
+            let mut routes = self.routes.write().unwrap();
+            routes.infos.remove(method);
+            routes.mapped.remove(method);
+            routes.methods.remove(method).is_some()
+        };
+        if removed {
+            self.notify(Change::Unregistered(method.to_string()));
+        }
+        removed
+    }
+    /// Swaps the handler of a registered `method`; returns `false`, without
+    /// registering anything, if there was none. Calls already running
+    /// finish with the old handler.
+    pub fn replace<F, E>(&self, method: &str, handle: Box<F>) -> bool
+        where F: Fn(&Option<Json>) -> Result<Json, E> + Send + Sync + ?Sized + 'static, E: Into<Error> {
+        match self.routes.write().unwrap().methods.get_mut(method) {
+            Some(slot) => *slot = Arc::new(into_handle(handle)),
+            None       => return false
+        }
+        self.notify(Change::Replaced(method.to_string()));
+        true
+    }
+    /// Returns a receiver of every later change to the registered methods,
+    /// e.g. for an introspection endpoint. Dropping it stops the updates.
+    pub fn watch(&self) -> Receiver<Change> {
+        let (sender, receiver) = channel();
+        self.watchers.lock().unwrap().push(sender);
+        receiver
+    }
+    pub(crate) fn notify(&self, change: Change) {
+        let mut watchers = self.watchers.lock().unwrap();
+        watchers.retain(|watcher| watcher.send(change.clone()).is_ok());
+    }
+}
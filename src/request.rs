@@ -2,14 +2,18 @@
 use std::collections::BTreeMap;
 use std::str::FromStr;
 use std::string::ToString;
-use ::{Json, ToJson, Object, Error};
+use ::{Json, ToJson, Object, Error, Mode, Deviation, Version, Id, TraceContext};
+use protocol;
+use trace::TRACEPARENT;
 
 #[derive(Debug, Clone)]
 pub struct Request {
     jsonrpc: String,
     method : String,
     params : Option<Json>,
-    id     : Option<i64>
+    id     : Option<Id>,
+    notification: bool,
+    trace  : Option<TraceContext>
 }
 
 impl FromStr for Request {
@@ -30,10 +34,32 @@ impl FromStr for Request {
 impl ToJson for Request {
     fn to_json(&self) -> Json {
         let mut json = BTreeMap::new();
+        if self.version() == Version::V1 {
+            // 1.0 params are always an array, and notifications have a null id.
+            let params = match self.params {
+                Some(Json::Array(ref params)) => params.clone(),
+                Some(ref params)              => vec![params.clone()],
+                None                          => Vec::new()
+            };
+            json.insert("method".to_string(), self.method.to_json()  );
+            json.insert("params".to_string(), Json::Array(params)    );
+            json.insert("id".to_string(),     self.id.to_json()      );
+            if let Some(ref trace) = self.trace {
+                json.insert(TRACEPARENT.to_string(), trace.to_string().to_json());
+            }
+            return Json::Object(json);
+        }
         json.insert("jsonrpc".to_string(), self.jsonrpc.to_json() );
         json.insert("method".to_string(),  self.method.to_json()  );
-        json.insert("params".to_string(),  self.params.to_json()  );
-        json.insert("id".to_string(),      self.id.to_json()      );
+        if let Some(ref params) = self.params {
+            json.insert("params".to_string(), params.clone()   );
+        }
+        if !self.notification {
+            json.insert("id".to_string(),  self.id.to_json()      );
+        }
+        if let Some(ref trace) = self.trace {
+            json.insert(TRACEPARENT.to_string(), trace.to_string().to_json());
+        }
         Json::Object(json)
     }
 }
@@ -45,63 +71,137 @@ impl ToString for Request {
 }
 
 impl Request {
-    pub fn new(jsonrpc: &str, method: &str, params: Option<Json>, id: Option<i64>) -> Request {
+    pub fn new(jsonrpc: &str, method: &str, params: Option<Json>, id: Option<Id>) -> Request {
         Request{
             jsonrpc: jsonrpc.to_string(),
             method : method.to_string(),
             params : params,
-            id     : id
+            id     : id,
+            notification: false,
+            trace  : None
         }
     }
-    pub fn parse(j: Json) -> Result<Request, Error> {
-        if !j.is_object() {
-            return Err(Error::ParseError);
+    /// Builds a notification: a request without an `id`, which the server
+    /// must not answer.
+    pub fn notification(jsonrpc: &str, method: &str, params: Option<Json>) -> Request {
+        Request{
+            jsonrpc: jsonrpc.to_string(),
+            method : method.to_string(),
+            params : params,
+            id     : None,
+            notification: true,
+            trace  : None
         }
+    }
+    /// Parses a request object as described by the JSON-RPC 2.0 spec, in
+    /// `Mode::Strict`.
+    pub fn parse(j: Json) -> Result<Request, Error> {
+        Request::parse_with(j, Mode::Strict)
+    }
+    /// Parses a request object as described by the JSON-RPC 2.0 spec.
+    ///
+    /// Anything that is not a well-formed request object is an
+    /// `InvalidRequest`: a non-object, a `jsonrpc` other than `"2.0"`, a
+    /// missing or non-string `method`, `params` that are not structured, or
+    /// an `id` that is not an integer, a string or null. `params` may be
+    /// omitted; in `Mode::Lenient`, `"params": null` is taken to mean the
+    /// same. A request without an `id` is a notification; one with
+    /// `"id": null` is not, and is answered with `"id": null`.
+    ///
+    /// Integer ids must fit in an `i64`; fractional ids are rejected as
+    /// `InvalidRequest`.
+    ///
+    /// `traceparent` is reserved for the W3C trace context of the call on
+    /// transports without headers. It must be a string; one that is not a
+    /// valid trace context is ignored, as the spec asks.
+    pub fn parse_with(j: Json, mode: Mode) -> Result<Request, Error> {
+        Request::inspect(j, mode).map(|(request, _)| request)
+    }
+    /// Like `parse_with`, also returning the deviations from the spec that
+    /// were tolerated.
+    ///
+    /// `Mode::Strict` accepts no deviations at all and rejects members the
+    /// spec does not define. `Mode::Lenient` tolerates the `Deviation`s: a
+    /// missing `jsonrpc` or one of `"2"`, `2`, `2.0`, `"params": null` and
+    /// unknown members.
+    pub fn inspect(j: Json, mode: Mode) -> Result<(Request, Vec<Deviation>), Error> {
+        let obj = match j.as_object() {
+            Some(obj) => obj,
+            None      => return Err(Error::InvalidRequest)
+        };
 
-        let obj = j.as_object().unwrap();
-
-        let version = Request::_parse_version(&obj);
+        let mut deviations = Vec::new();
+        let version = protocol::parse_version(&obj, mode, &mut deviations);
         let method  = Request::_parse_method(&obj);
         let params  = Request::_parse_params(&obj);
-        let id      = Request::_parse_id(&obj);
+        let notification = !obj.contains_key("id");
+        let id      = match notification {
+            true  => Ok(None),
+            false => protocol::parse_id(&obj)
+        };
+        let trace   = Request::_parse_trace(&obj);
+        let members = protocol::check_members(&obj, &["jsonrpc", "method", "params", "id", TRACEPARENT], mode, &mut deviations);
 
-        if version.is_err() && id.is_err() {
+        if version.is_err() || method.is_err() || params.is_err() || id.is_err() || trace.is_err() || members.is_err() {
             return Err(Error::InvalidRequest);
         }
-        if method.is_err() {
-            return Err(Error::MethodNotFound);
+        if obj.get("params") == Some(&Json::Null) {
+            match mode {
+                Mode::Strict  => return Err(Error::InvalidRequest),
+                Mode::Lenient => deviations.push(Deviation::NullParams)
+            }
         }
-        if params.is_err() {
-            return Err(Error::InvalidParams);
+        let request = Request {
+            jsonrpc: version.ok().unwrap(),
+            method : method.ok().unwrap(),
+            params : params.ok().unwrap(),
+            id     : id.ok().unwrap(),
+            notification: notification,
+            trace  : trace.ok().unwrap()
+        };
+        Ok((request, deviations))
+    }
+    /// Parses a JSON-RPC 1.0 request: an object with a string `method`,
+    /// `params` that is an array and an `id`, where `"id": null` makes it a
+    /// notification. A `jsonrpc` member is ignored if present.
+    pub fn parse_v1(j: Json) -> Result<Request, Error> {
+        let obj = match j.as_object() {
+            Some(obj) => obj,
+            None      => return Err(Error::InvalidRequest)
+        };
+        let method = Request::_parse_method(&obj);
+        let params = match obj.get("params") {
+            Some(&Json::Array(ref params)) => Ok(Some(Json::Array(params.clone()))),
+            _                              => Err(())
+        };
+        let id     = Request::_parse_id(&obj);
+        let trace  = Request::_parse_trace(&obj);
+        if method.is_err() || params.is_err() || id.is_err() || trace.is_err() {
+            return Err(Error::InvalidRequest);
         }
+        let id = id.ok().unwrap();
         Ok(Request {
-            jsonrpc: version.ok().unwrap(),
+            jsonrpc: Version::V1.as_str().to_string(),
             method : method.ok().unwrap(),
             params : params.ok().unwrap(),
-            id     : id.ok().unwrap()
+            notification: id.is_none(),
+            id     : id,
+            trace  : trace.ok().unwrap()
         })
     }
+    /// The `id` of a request object that could not be parsed, if it can be
+    /// read at all, for use in the error response.
+    pub fn parse_id(j: &Json) -> Option<Id> {
+        j.as_object().and_then(|obj| Request::_parse_id(obj).ok()).and_then(|id| id)
+    }
     pub fn _parse_version (obj: &Object) -> Result<String, ()> {
-        match obj.get("jsonrpc") {
-            Some(version) => {
-                match version.as_string().unwrap() {
-                    // JsonRpc Version Must Be 2.0. 
-                    "2.0" | "2" => Ok("2.0".to_string()),
-                    _           => Err(())
-                }
-            },
-            None => Err(())
-        }
+        // JsonRpc Version Must Be 2.0.
+        protocol::parse_version(obj, Mode::Strict, &mut Vec::new())
     }
     pub fn _parse_method (obj: &Object) -> Result<String, ()> {
-        match obj.get("method") {
-            Some(method) => {
-                match method.is_string() {
-                    true  => Ok(method.as_string().unwrap().to_string()),
-                    false => Err(())
-                }
-            },
-            None => Err(())
+        match obj.get("method").and_then(|method| method.as_string()) {
+            Some(method) => Ok(method.to_string()),
+            None         => Err(())
         }
     }
     pub fn _parse_params (obj: &Object) -> Result<Option<Json>, ()> {
@@ -115,36 +215,50 @@ impl Request {
                     Err(())
                 }
             },
-            None => Err(())
+            None => Ok(None)
         }
     }
-    pub fn _parse_id (obj: &Object) -> Result<Option<i64>, ()> {
-        match obj.get("id") {
-            Some(id) => {
-                // i.is_number() || i.is_u64()
-                if id.is_i64() || id.is_u64() {
-                    Ok(Some(id.as_i64().unwrap()))
-                } else if id.is_null() {
-                    Ok(None)
-                } else {
-                    Err(())
-                }
-            },
-            None => Err(())
+    pub fn _parse_id (obj: &Object) -> Result<Option<Id>, ()> {
+        protocol::parse_id(obj)
+    }
+    fn _parse_trace (obj: &Object) -> Result<Option<TraceContext>, ()> {
+        match obj.get(TRACEPARENT) {
+            Some(&Json::String(ref traceparent)) => Ok(TraceContext::parse(traceparent)),
+            Some(_)                              => Err(()),
+            None                                 => Ok(None)
         }
     }
 
     pub fn jsonrpc(&self) -> String {
         self.jsonrpc.clone()
     }
+    pub fn version(&self) -> Version {
+        Version::of(&self.jsonrpc)
+    }
     pub fn method(&self) -> String {
         self.method.clone()
     }
+    pub fn set_method(&mut self, method: &str) {
+        self.method = method.to_string();
+    }
     pub fn params(&self) -> Option<Json> {
         self.params.clone()
     }
-    pub fn id(&self) -> Option<i64> {
+    pub fn set_params(&mut self, params: Option<Json>) {
+        self.params = params;
+    }
+    pub fn id(&self) -> Option<Id> {
         self.id.clone()
     }
+    pub fn is_notification(&self) -> bool {
+        self.notification
+    }
+    /// The trace context the request was sent with, if any.
+    pub fn trace(&self) -> Option<&TraceContext> {
+        self.trace.as_ref()
+    }
+    pub fn set_trace(&mut self, trace: Option<TraceContext>) {
+        self.trace = trace;
+    }
 }
 
@@ -1,18 +1,63 @@
 
 use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
 use std::str::FromStr;
 use std::string::ToString;
-use ::{Json, ToJson, Object, Error, Request};
+use ::{Json, ToJson, Object, Error, Request, Mode, Deviation, Version, Id};
+use protocol;
 
 #[derive(Debug, Clone)]
 pub enum Response {
-    Success{jsonrpc: String, result: Option<Json>, id: Option<i64>},
-    Error  {jsonrpc: String, error : Error,        id: Option<i64>}
+    Success{jsonrpc: String, result: Option<Json>, id: Option<Id>},
+    Error  {jsonrpc: String, error : Error,        id: Option<Id>}
 }
 
+/// Why a response could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseError {
+    /// The body is not valid JSON.
+    Syntax(String),
+    /// The response is not a JSON object.
+    NotObject,
+    /// The `jsonrpc` member is missing or not `"2.0"`.
+    Version(Option<Json>),
+    /// The `id` member is missing.
+    MissingId,
+    /// The `id` is neither an integer, a string nor null.
+    InvalidId(Json),
+    /// Neither `result` nor `error` is present.
+    MissingResult,
+    /// Both `result` and `error` are present.
+    ResultAndError,
+    /// The `error` member is not a valid error object.
+    InvalidError(&'static str),
+    /// A top-level member the spec does not define.
+    UnknownMember(String)
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResponseError::Syntax(ref err)       => write!(f, "invalid JSON: {}", err),
+            ResponseError::NotObject             => write!(f, "response is not an object"),
+            ResponseError::Version(None)         => write!(f, "missing \"jsonrpc\" member"),
+            ResponseError::Version(Some(ref v))  => write!(f, "\"jsonrpc\" is {} instead of \"2.0\"", v),
+            ResponseError::MissingId             => write!(f, "missing \"id\" member"),
+            ResponseError::InvalidId(ref id)     => write!(f, "\"id\" is {} instead of an integer, a string or null", id),
+            ResponseError::MissingResult         => write!(f, "neither \"result\" nor \"error\" is present"),
+            ResponseError::ResultAndError        => write!(f, "both \"result\" and \"error\" are present"),
+            ResponseError::InvalidError(reason)  => write!(f, "invalid error object: {}", reason),
+            ResponseError::UnknownMember(ref m)  => write!(f, "unknown member \"{}\"", m)
+        }
+    }
+}
+
+impl error::Error for ResponseError {}
+
 impl ToJson for Response {
     fn to_json(&self) -> Json {
-        match *self {
+        let json = match *self {
             Response::Success{
                 jsonrpc: ref jsonrpc,
                 result : ref result,
@@ -33,6 +78,16 @@ impl ToJson for Response {
                     json.insert("id".to_string(),      id.to_json()      );
                     Json::Object(json)
             }
+        };
+        match (self.version(), json) {
+            // 1.0 responses carry both `result` and `error`, one of them null.
+            (Version::V1, Json::Object(mut json)) => {
+                json.remove("jsonrpc");
+                json.entry("result".to_string()).or_insert(Json::Null);
+                json.entry("error".to_string()).or_insert(Json::Null);
+                Json::Object(json)
+            },
+            (_, json) => json
         }
     }
 }
@@ -44,63 +99,119 @@ impl ToString for Response {
 }
 
 impl FromStr for Response {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Response, Error> {
+    type Err = ResponseError;
+    fn from_str(s: &str) -> Result<Response, ResponseError> {
         match Json::from_str(s) {
-            Ok(json) => {
-                match Response::parse(json) {
-                    Ok(response) => Ok(response),
-                    Err(err)    => Err(err)
-                }
-            },
-            Err(_)  => Err(Error::ParseError)
+            Ok(json) => Response::parse(json),
+            Err(err) => Err(ResponseError::Syntax(err.to_string()))
         }
     }
 }
 
 impl Response {
-    pub fn parse(j: Json) -> Result<Response, Error> {
-        if !j.is_object() {
-            return Err(Error::ParseError);
+    /// A JSON-RPC 2.0 error response, e.g. for a request that could not be
+    /// parsed. `id` is `None` when the request id could not be read.
+    pub fn error(error: Error, id: Option<Id>) -> Response {
+        Response::Error{ jsonrpc: "2.0".to_string(), error: error, id: id }
+    }
+    pub fn jsonrpc(&self) -> String {
+        match *self {
+            Response::Success{ ref jsonrpc, .. } => jsonrpc.clone(),
+            Response::Error{ ref jsonrpc, .. }   => jsonrpc.clone()
         }
-        let obj = j.as_object().unwrap();
-
-        let version = Request::_parse_version(&obj);
-        let id      = Request::_parse_id(&obj);
-
-        let result  = Response::_parse_result(&obj);
-        let error   = Error::_parse_error(&obj);
-        
-
-        if version.is_err() && id.is_err() {
-            return Err(Error::InternalError);
+    }
+    pub fn version(&self) -> Version {
+        Version::of(&self.jsonrpc())
+    }
+    /// Parses a JSON-RPC 1.0 response: an object with `result`, `error` and
+    /// `id`, where a non-null `error` makes it an error response. Either of
+    /// `result` and `error` may be left out when it would be null.
+    pub fn parse_v1(j: Json) -> Result<Response, ResponseError> {
+        let obj = match j.as_object() {
+            Some(obj) => obj,
+            None      => return Err(ResponseError::NotObject)
+        };
+        let id = Response::_parse_id(&obj)?;
+        let jsonrpc = Version::V1.as_str().to_string();
+        match obj.get("error") {
+            None | Some(&Json::Null) => {
+                let result = Response::_parse_result(&obj).unwrap_or(None);
+                Ok(Response::Success{ jsonrpc: jsonrpc, result: result, id: id })
+            },
+            // Not every 1.0 service sends error objects; a plain string
+            // becomes the message of an unregistered error.
+            Some(&Json::String(ref message)) => {
+                let error = Error::Unregister(0, message.clone(), None);
+                Ok(Response::Error{ jsonrpc: jsonrpc, error: error, id: id })
+            },
+            Some(_) => {
+                let error = Response::_parse_error(&obj)?;
+                Ok(Response::Error{ jsonrpc: jsonrpc, error: error, id: id })
+            }
         }
+    }
+    /// Parses a response object in `Mode::Strict`.
+    pub fn parse(j: Json) -> Result<Response, ResponseError> {
+        Response::parse_with(j, Mode::Strict)
+    }
+    pub fn parse_with(j: Json, mode: Mode) -> Result<Response, ResponseError> {
+        Response::inspect(j, mode).map(|(response, _)| response)
+    }
+    /// Like `parse_with`, also returning the deviations from the spec that
+    /// were tolerated; see `Request::inspect`. Lenient mode additionally
+    /// accepts a `null` `result` or `error` next to the other one.
+    pub fn inspect(j: Json, mode: Mode) -> Result<(Response, Vec<Deviation>), ResponseError> {
+        let obj = match j.as_object() {
+            Some(obj) => obj,
+            None      => return Err(ResponseError::NotObject)
+        };
 
-        if result.is_err() && error.is_err() {
-            return Err(Error::InternalError);
+        let mut deviations = Vec::new();
+        let jsonrpc = match protocol::parse_version(&obj, mode, &mut deviations) {
+            Ok(jsonrpc) => jsonrpc,
+            Err(_)      => return Err(ResponseError::Version(obj.get("jsonrpc").cloned()))
+        };
+        let id = Response::_parse_id(&obj)?;
+        if let Err(member) = protocol::check_members(&obj, &["jsonrpc", "result", "error", "id"], mode, &mut deviations) {
+            return Err(ResponseError::UnknownMember(member));
         }
-        if result.is_err() && error.is_ok() {
-            let (code, message, data) = error.ok().unwrap();
-            let mut error = Error::from_i64(code).unwrap();
-            error.set_message(message);
-            error.set_data(data);
-            
-            return Ok(Response::Error{
-                jsonrpc: version.ok().unwrap(),
-                error  : error,
-                id     : id.ok().unwrap()
-            })
-        }
-        if result.is_ok() && error.is_err() {
-            return Ok(Response::Success{
-                jsonrpc: version.ok().unwrap(),
-                result : result.ok().unwrap(),
-                id     : id.ok().unwrap()
-            })
-        } else {
-            return Err(Error::InternalError);
+
+        let (result, error) = match (obj.get("result"), obj.get("error")) {
+            (Some(_), Some(&Json::Null)) if mode == Mode::Lenient => {
+                deviations.push(Deviation::NullMember("error".to_string()));
+                (true, false)
+            },
+            (Some(&Json::Null), Some(_)) if mode == Mode::Lenient => {
+                deviations.push(Deviation::NullMember("result".to_string()));
+                (false, true)
+            },
+            (Some(_), Some(_)) => return Err(ResponseError::ResultAndError),
+            (None, None)       => return Err(ResponseError::MissingResult),
+            (result, error)    => (result.is_some(), error.is_some())
+        };
+        let response = match (result, error) {
+            (_, true) => Response::Error{
+                jsonrpc: jsonrpc,
+                error  : Response::_parse_error(&obj)?,
+                id     : id
+            },
+            _ => Response::Success{
+                jsonrpc: jsonrpc,
+                result : Response::_parse_result(&obj).unwrap_or(None),
+                id     : id
+            }
+        };
+        Ok((response, deviations))
+    }
+    fn _parse_id(obj: &Object) -> Result<Option<Id>, ResponseError> {
+        match obj.get("id") {
+            Some(id) => protocol::parse_id(obj).map_err(|_| ResponseError::InvalidId(id.clone())),
+            None     => Err(ResponseError::MissingId)
         }
-        
+    }
+    fn _parse_error(obj: &Object) -> Result<Error, ResponseError> {
+        let (code, message, data) = Error::_parse_error(obj).map_err(ResponseError::InvalidError)?;
+        Ok(Error::new(code, &message, data))
     }
     pub fn _parse_result(obj: &Object) -> Result<Option<Json>, ()> {
         match obj.get("result") {
@@ -0,0 +1,214 @@
+
+use std::collections::BTreeMap;
+use ::{Json, ToJson};
+
+/// One failed check of `validate`, located by a JSON Pointer (RFC 6901)
+/// into the validated value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaError {
+    pointer: String,
+    message: String
+}
+
+impl SchemaError {
+    pub fn new(pointer: &str, message: &str) -> SchemaError {
+        SchemaError { pointer: pointer.to_string(), message: message.to_string() }
+    }
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl ToJson for SchemaError {
+    fn to_json(&self) -> Json {
+        let mut json = BTreeMap::new();
+        json.insert("pointer".to_string(), self.pointer.to_json() );
+        json.insert("message".to_string(), self.message.to_json() );
+        Json::Object(json)
+    }
+}
+
+/// Appends one reference token to a JSON Pointer.
+pub fn pointer_push(pointer: &str, token: &str) -> String {
+    pointer.to_string() + "/" + &token.replace("~", "~0").replace("/", "~1")
+}
+
+/// Validates `value` against a JSON Schema.
+///
+/// The supported subset covers what method params and results need:
+/// boolean schemas, `type`, `enum`, `const`, `minimum`, `maximum`,
+/// `exclusiveMinimum`, `exclusiveMaximum`, `minLength`, `maxLength`,
+/// `items`, `minItems`, `maxItems`, `properties`, `required`,
+/// `additionalProperties`, `allOf`, `anyOf`, `oneOf` and `not`. Other
+/// keywords, including `$ref`, `pattern` and `format`, are ignored.
+pub fn validate(schema: &Json, value: &Json) -> Vec<SchemaError> {
+    let mut errors = Vec::new();
+    validate_at(schema, value, "", &mut errors);
+    errors
+}
+
+fn validate_at(schema: &Json, value: &Json, pointer: &str, errors: &mut Vec<SchemaError>) {
+    let schema = match *schema {
+        Json::Boolean(true)  => return,
+        Json::Boolean(false) => return errors.push(SchemaError::new(pointer, "no value is allowed here")),
+        Json::Object(ref schema) => schema,
+        _ => return
+    };
+
+    if let Some(types) = schema.get("type") {
+        let types = match *types {
+            Json::Array(ref types) => types.iter().filter_map(|t| t.as_string()).collect::<Vec<&str>>(),
+            Json::String(ref t)    => vec![t.as_ref()],
+            _                      => vec![]
+        };
+        if !types.is_empty() && !types.iter().any(|t| is_type(value, t)) {
+            errors.push(SchemaError::new(pointer, &format!("expected {}", types.join(" or "))));
+            return;
+        }
+    }
+    if let Some(&Json::Array(ref values)) = schema.get("enum") {
+        if !values.iter().any(|v| equals(v, value)) {
+            errors.push(SchemaError::new(pointer, "not one of the allowed values"));
+        }
+    }
+    if let Some(constant) = schema.get("const") {
+        if !equals(constant, value) {
+            errors.push(SchemaError::new(pointer, &format!("expected {}", constant)));
+        }
+    }
+
+    if let Some(n) = value.as_f64() {
+        let limit = |key: &str| schema.get(key).and_then(|limit| limit.as_f64());
+        if let Some(min) = limit("minimum") {
+            if n < min { errors.push(SchemaError::new(pointer, &format!("must be >= {}", min))); }
+        }
+        if let Some(max) = limit("maximum") {
+            if n > max { errors.push(SchemaError::new(pointer, &format!("must be <= {}", max))); }
+        }
+        if let Some(min) = limit("exclusiveMinimum") {
+            if n <= min { errors.push(SchemaError::new(pointer, &format!("must be > {}", min))); }
+        }
+        if let Some(max) = limit("exclusiveMaximum") {
+            if n >= max { errors.push(SchemaError::new(pointer, &format!("must be < {}", max))); }
+        }
+    }
+
+    if let Some(s) = value.as_string() {
+        let length = s.chars().count() as u64;
+        if let Some(min) = schema.get("minLength").and_then(|n| n.as_u64()) {
+            if length < min { errors.push(SchemaError::new(pointer, &format!("must be at least {} characters", min))); }
+        }
+        if let Some(max) = schema.get("maxLength").and_then(|n| n.as_u64()) {
+            if length > max { errors.push(SchemaError::new(pointer, &format!("must be at most {} characters", max))); }
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        let length = items.len() as u64;
+        if let Some(min) = schema.get("minItems").and_then(|n| n.as_u64()) {
+            if length < min { errors.push(SchemaError::new(pointer, &format!("must have at least {} items", min))); }
+        }
+        if let Some(max) = schema.get("maxItems").and_then(|n| n.as_u64()) {
+            if length > max { errors.push(SchemaError::new(pointer, &format!("must have at most {} items", max))); }
+        }
+        match schema.get("items") {
+            Some(&Json::Array(ref schemas)) => {
+                for (i, (schema, item)) in schemas.iter().zip(items.iter()).enumerate() {
+                    validate_at(schema, item, &pointer_push(pointer, &i.to_string()), errors);
+                }
+            },
+            Some(schema) => {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(schema, item, &pointer_push(pointer, &i.to_string()), errors);
+                }
+            },
+            None => {}
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        if let Some(&Json::Array(ref required)) = schema.get("required") {
+            for name in required.iter().filter_map(|name| name.as_string()) {
+                if !object.contains_key(name) {
+                    errors.push(SchemaError::new(&pointer_push(pointer, name), "is required"));
+                }
+            }
+        }
+        let properties = match schema.get("properties") {
+            Some(&Json::Object(ref properties)) => Some(properties),
+            _                                   => None
+        };
+        for (name, item) in object.iter() {
+            let item_pointer = pointer_push(pointer, name);
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(schema) => validate_at(schema, item, &item_pointer, errors),
+                None => match schema.get("additionalProperties") {
+                    Some(&Json::Boolean(false)) => {
+                        errors.push(SchemaError::new(&item_pointer, "is not allowed"));
+                    },
+                    Some(schema) => validate_at(schema, item, &item_pointer, errors),
+                    None         => {}
+                }
+            }
+        }
+    }
+
+    if let Some(&Json::Array(ref schemas)) = schema.get("allOf") {
+        for schema in schemas {
+            validate_at(schema, value, pointer, errors);
+        }
+    }
+    if let Some(&Json::Array(ref schemas)) = schema.get("anyOf") {
+        if !schemas.iter().any(|schema| validate(schema, value).is_empty()) {
+            errors.push(SchemaError::new(pointer, "does not match any of the allowed schemas"));
+        }
+    }
+    if let Some(&Json::Array(ref schemas)) = schema.get("oneOf") {
+        if schemas.iter().filter(|schema| validate(schema, value).is_empty()).count() != 1 {
+            errors.push(SchemaError::new(pointer, "does not match exactly one of the allowed schemas"));
+        }
+    }
+    if let Some(schema) = schema.get("not") {
+        if validate(schema, value).is_empty() {
+            errors.push(SchemaError::new(pointer, "matches a schema it must not match"));
+        }
+    }
+}
+
+fn is_type(value: &Json, t: &str) -> bool {
+    match t {
+        "null"    => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string"  => value.is_string(),
+        "array"   => value.is_array(),
+        "object"  => value.is_object(),
+        "number"  => value.is_number(),
+        "integer" => match *value {
+            Json::I64(_) | Json::U64(_) => true,
+            Json::F64(n)                => n.fract() == 0.0,
+            _                           => false
+        },
+        _ => false
+    }
+}
+
+/// JSON equality, under which `1` and `1.0` are the same number.
+fn equals(a: &Json, b: &Json) -> bool {
+    match (a.is_number() && b.is_number(), a, b) {
+        (true, _, _) => match (a.as_i64(), b.as_i64(), a.as_u64(), b.as_u64()) {
+            (Some(x), Some(y), _, _) => x == y,
+            (_, _, Some(x), Some(y)) => x == y,
+            _                        => a.as_f64() == b.as_f64()
+        },
+        (_, &Json::Array(ref x), &Json::Array(ref y)) => {
+            x.len() == y.len() && x.iter().zip(y.iter()).all(|(x, y)| equals(x, y))
+        },
+        (_, &Json::Object(ref x), &Json::Object(ref y)) => {
+            x.len() == y.len() && x.iter().all(|(k, v)| y.get(k).map_or(false, |w| equals(v, w)))
+        },
+        _ => a == b
+    }
+}
@@ -0,0 +1,119 @@
+extern crate hyper;
+
+use std::io::Read;
+use std::str;
+use std::sync::Arc;
+use self::hyper::method::Method;
+use self::hyper::status::StatusCode;
+use self::hyper::header::ContentType;
+#[cfg(feature = "metrics")]
+use self::hyper::uri::RequestUri;
+use self::hyper::server::{Handler, Request as HttpRequest, Response as HttpResponse};
+use ::{JsonRpc, Context, TraceContext, TRACEPARENT};
+use auth::Credentials;
+
+/// Serves a `JsonRpc` over HTTP with hyper.
+///
+/// ```ignore
+/// let handler = HttpHandler::new(Arc::new(rpc));
+/// Server::http("0.0.0.0:8000").unwrap().handle(handler).unwrap();
+/// ```
+///
+/// `POST` and `PUT` bodies are passed to `JsonRpc::handle_with`, with the
+/// peer address, the credentials of the `Authorization` header and the
+/// trace context of the `traceparent` header in the call `Context`.
+/// Warnings recorded in the context, such as deprecation notices, are sent
+/// back in `Warning` headers.
+pub struct HttpHandler {
+    rpc: Arc<JsonRpc>,
+    #[cfg(feature = "metrics")]
+    metrics_path: Option<String>
+}
+
+impl HttpHandler {
+    pub fn new(rpc: Arc<JsonRpc>) -> HttpHandler {
+        HttpHandler {
+            rpc: rpc,
+            #[cfg(feature = "metrics")]
+            metrics_path: None
+        }
+    }
+    pub fn rpc(&self) -> &Arc<JsonRpc> {
+        &self.rpc
+    }
+    /// Answers `GET` requests for `path`, e.g. `/metrics`, with the
+    /// metrics of the `JsonRpc` in the Prometheus text format.
+    #[cfg(feature = "metrics")]
+    pub fn serve_metrics(mut self, path: &str) -> HttpHandler {
+        self.metrics_path = Some(path.to_string());
+        self
+    }
+    #[cfg(feature = "metrics")]
+    fn is_metrics(&self, req: &HttpRequest) -> bool {
+        match (&req.method, &req.uri, &self.metrics_path) {
+            (&Method::Get, &RequestUri::AbsolutePath(ref uri), &Some(ref path)) => {
+                uri.split('?').next() == Some(path.as_str())
+            },
+            _ => false
+        }
+    }
+    fn context(&self, req: &HttpRequest) -> Context {
+        let mut ctx = Context::with_peer(&req.remote_addr.to_string());
+        let header = |name: &str| req.headers.get_raw(name)
+                                             .and_then(|values| values.first())
+                                             .and_then(|value| str::from_utf8(value).ok());
+        if let Some(credentials) = header("Authorization").and_then(Credentials::from_header) {
+            ctx.set_credentials(credentials);
+        }
+        if let Some(trace) = header(TRACEPARENT).and_then(TraceContext::parse) {
+            ctx.set_trace(trace);
+        }
+        ctx
+    }
+}
+
+impl Handler for HttpHandler {
+    fn handle(&self, mut req: HttpRequest, mut res: HttpResponse) {
+        #[cfg(feature = "metrics")]
+        {
+            if self.is_metrics(&req) {
+                res.headers_mut().set_raw("Content-Type", vec![b"text/plain; version=0.0.4".to_vec()]);
+                if let Err(err) = res.send(self.rpc.metrics().render().as_bytes()) {
+                    warn!("could not send the metrics to {}: {}", req.remote_addr, err);
+                }
+                return;
+            }
+        }
+        match req.method {
+            Method::Post | Method::Put => {},
+            _ => {
+                *res.status_mut() = StatusCode::MethodNotAllowed;
+                return;
+            }
+        }
+        let mut body = String::new();
+        if let Err(err) = req.read_to_string(&mut body) {
+            warn!("could not read the request from {}: {}", req.remote_addr, err);
+            *res.status_mut() = StatusCode::BadRequest;
+            return;
+        }
+        let mut ctx = self.context(&req);
+        let response = self.rpc.handle_with(&body, &mut ctx);
+        if !ctx.warnings().is_empty() {
+            let warnings = ctx.warnings().iter()
+                                         .map(|warning| format!("299 - \"{}\"", warning.replace("\"", "'")).into_bytes())
+                                         .collect::<Vec<Vec<u8>>>();
+            res.headers_mut().set_raw("Warning", warnings);
+        }
+        match response {
+            Some(body) => {
+                res.headers_mut().set(ContentType::json());
+                if let Err(err) = res.send(body.as_bytes()) {
+                    warn!("could not send the response to {}: {}", req.remote_addr, err);
+                }
+            },
+            // Only notifications; there is nothing to answer.
+            None => *res.status_mut() = StatusCode::NoContent
+        }
+    }
+}
@@ -0,0 +1,203 @@
+
+use std::fmt;
+use getrandom::getrandom;
+#[cfg(feature = "tracing")]
+use std::cell::RefCell;
+#[cfg(feature = "tracing")]
+use tracing::{self, Span};
+#[cfg(feature = "tracing")]
+use tracing::field::Empty;
+#[cfg(feature = "tracing")]
+use ::{Request, Response, Context};
+
+/// Name of the HTTP header, and of the request member on other transports,
+/// that carries the trace context of a call.
+pub const TRACEPARENT: &'static str = "traceparent";
+
+// 64 bits from the randomness of the operating system; panics if there is
+// none to give.
+fn random() -> u64 {
+    loop {
+        let mut bytes = [0u8; 8];
+        getrandom(&mut bytes).expect("no randomness for trace ids");
+        // All-zero ids are invalid.
+        match bytes.iter().fold(0, |id, &byte| id << 8 | byte as u64) {
+            0  => continue,
+            id => return id
+        }
+    }
+}
+
+fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| b.is_ascii_digit() || (b'a' ..= b'f').contains(&b))
+}
+
+/// A W3C trace context: the trace a call belongs to and the span that made
+/// it, as sent in `traceparent`, e.g.
+/// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceContext {
+    trace_id: String,
+    span_id : String,
+    sampled : bool
+}
+
+impl TraceContext {
+    /// Starts a new trace.
+    pub fn new() -> TraceContext {
+        TraceContext {
+            trace_id: format!("{:016x}{:016x}", random(), random()),
+            span_id : format!("{:016x}", random()),
+            sampled : true
+        }
+    }
+    /// Parses a `traceparent` value; `None` if it is not valid, in which
+    /// case the spec has the callee start a new trace.
+    pub fn parse(traceparent: &str) -> Option<TraceContext> {
+        let parts = traceparent.trim().split('-').collect::<Vec<&str>>();
+        if parts.len() < 4 {
+            return None;
+        }
+        let (version, trace_id, span_id, flags) = (parts[0], parts[1], parts[2], parts[3]);
+        // Later versions may append fields, version 00 may not.
+        let valid = is_hex(version, 2) && version != "ff" && (version != "00" || parts.len() == 4)
+                 && is_hex(trace_id, 32) && trace_id.bytes().any(|b| b != b'0')
+                 && is_hex(span_id, 16) && span_id.bytes().any(|b| b != b'0')
+                 && is_hex(flags, 2);
+        match valid {
+            true  => Some(TraceContext {
+                trace_id: trace_id.to_string(),
+                span_id : span_id.to_string(),
+                sampled : u8::from_str_radix(flags, 16).unwrap_or(0) & 1 == 1
+            }),
+            false => None
+        }
+    }
+    /// The context of a span started within this one: same trace, new
+    /// span id.
+    pub fn child(&self) -> TraceContext {
+        TraceContext { span_id: format!("{:016x}", random()), ..self.clone() }
+    }
+    pub fn trace_id(&self) -> &str {
+        &self.trace_id
+    }
+    pub fn span_id(&self) -> &str {
+        &self.span_id
+    }
+    pub fn sampled(&self) -> bool {
+        self.sampled
+    }
+    /// The trace context of the call being dispatched on this thread,
+    /// which calls made from its handler continue. `Client` does so by
+    /// itself.
+    #[cfg(feature = "tracing")]
+    pub fn current() -> Option<TraceContext> {
+        CURRENT.with(|current| current.borrow().last().cloned())
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "00-{}-{}-{}", self.trace_id, self.span_id, if self.sampled { "01" } else { "00" })
+    }
+}
+
+#[cfg(feature = "tracing")]
+thread_local! {
+    // The trace contexts of the calls dispatched on this thread, innermost
+    // last.
+    static CURRENT: RefCell<Vec<TraceContext>> = RefCell::new(Vec::new());
+}
+
+/// Keeps a trace context current until it is dropped.
+#[cfg(feature = "tracing")]
+pub(crate) struct Entered(());
+
+#[cfg(feature = "tracing")]
+impl Entered {
+    fn new(trace: TraceContext) -> Entered {
+        CURRENT.with(|current| current.borrow_mut().push(trace));
+        Entered(())
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Entered {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.borrow_mut().pop());
+    }
+}
+
+/// Starts the span of a call dispatched by a `JsonRpc` and makes its trace
+/// context current. The call continues the trace of the request, or of the
+/// transport, or starts a new one.
+#[cfg(feature = "tracing")]
+pub(crate) fn serve(request: &Request, ctx: &Context) -> (Span, Entered) {
+    let parent = request.trace().or(ctx.trace());
+    let trace = match parent {
+        Some(parent) => parent.child(),
+        None         => TraceContext::new()
+    };
+    let span = tracing::info_span!("jsonrpc.request",
+                                   method    = request.method().as_str(),
+                                   id        = Empty,
+                                   trace_id  = trace.trace_id(),
+                                   span_id   = trace.span_id(),
+                                   parent_id = Empty,
+                                   outcome   = Empty,
+                                   code      = Empty);
+    if let Some(id) = request.id() {
+        span.record("id", &id.to_string().as_str());
+    }
+    if let Some(parent) = parent {
+        span.record("parent_id", &parent.span_id());
+    }
+    (span, Entered::new(trace))
+}
+
+/// Starts the span of a call made by a `Client`, continuing the trace of
+/// the call being dispatched on this thread if there is one. Returns the
+/// trace context to send along.
+#[cfg(feature = "tracing")]
+pub(crate) fn send(method: &str, id: i64) -> (Span, TraceContext) {
+    let parent = TraceContext::current();
+    let trace = match parent {
+        Some(ref parent) => parent.child(),
+        None             => TraceContext::new()
+    };
+    let span = tracing::info_span!("jsonrpc.client",
+                                   method    = method,
+                                   id        = id,
+                                   trace_id  = trace.trace_id(),
+                                   span_id   = trace.span_id(),
+                                   parent_id = Empty,
+                                   outcome   = Empty,
+                                   code      = Empty,
+                                   reason    = Empty);
+    if let Some(parent) = parent {
+        span.record("parent_id", &parent.span_id());
+    }
+    (span, trace)
+}
+
+/// Records the outcome of a call on its span: `success`, or `error` with
+/// the error code.
+#[cfg(feature = "tracing")]
+pub(crate) fn respond(span: &Span, response: &Response) {
+    match *response {
+        Response::Success{ .. } => {
+            span.record("outcome", &"success");
+        },
+        Response::Error{ ref error, .. } => {
+            span.record("outcome", &"error");
+            span.record("code", &error.to_i64());
+        }
+    }
+}
+
+/// Records a call that got no valid response on its span.
+#[cfg(feature = "tracing")]
+pub(crate) fn fail(span: &Span, reason: &str) {
+    span.record("outcome", &"failed");
+    span.record("reason", &reason);
+}
@@ -0,0 +1,175 @@
+extern crate jsonrpc2;
+
+use std::str::FromStr;
+use jsonrpc2::{rpc, AppError, JsonRpc, Request, Client, ClientError, Error, Json, ToJson};
+
+pub type Point = (i64, i64);
+
+#[rpc]
+pub trait Geometry {
+    fn translate(&self, point: Point, dx: i64, dy: Option<i64>) -> Result<Point, &'static str>;
+    #[rpc(name = "geometry.origin")]
+    fn origin(&self) -> Result<Point, &'static str>;
+}
+
+struct Plane;
+
+impl Geometry for Plane {
+    fn translate(&self, point: Point, dx: i64, dy: Option<i64>) -> Result<Point, &'static str> {
+        Ok((point.0 + dx, point.1 + dy.unwrap_or(0)))
+    }
+    fn origin(&self) -> Result<Point, &'static str> {
+        Ok((0, 0))
+    }
+}
+
+fn call(rpc: &JsonRpc, body: &str) -> String {
+    rpc.call(&Request::from_str(body).unwrap()).to_string()
+}
+
+#[test]
+fn test_register(){
+    let rpc = JsonRpc::new();
+    register_geometry(&rpc, Plane);
+    assert_eq!(rpc.methods().keys().collect::<Vec<&String>>(), vec!["geometry.origin", "translate"]);
+
+    let res = call(&rpc, "{\"jsonrpc\": \"2.0\", \"method\": \"translate\", \"params\": [[1, 2], 3, 4], \"id\": 1}");
+    assert_eq!(&res, "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":[4,6]}");
+    let res = call(&rpc, "{\"jsonrpc\": \"2.0\", \"method\": \"translate\", \"params\": {\"dx\": 3, \"point\": [1, 2]}, \"id\": 2}");
+    assert_eq!(&res, "{\"id\":2,\"jsonrpc\":\"2.0\",\"result\":[4,2]}");
+    let res = call(&rpc, "{\"jsonrpc\": \"2.0\", \"method\": \"geometry.origin\", \"params\": [], \"id\": 3}");
+    assert_eq!(&res, "{\"id\":3,\"jsonrpc\":\"2.0\",\"result\":[0,0]}");
+    let res = call(&rpc, "{\"jsonrpc\": \"2.0\", \"method\": \"translate\", \"params\": [[1], 3], \"id\": 4}");
+    assert_eq!(&res, "{\"error\":{\"code\":-32602,\"data\":\"ExpectedError(\\\"Tuple2\\\", \\\"Tuple1\\\")\",\
+                       \"message\":\"Invalid method parameter(s)\"},\"id\":4,\"jsonrpc\":\"2.0\"}");
+}
+
+#[test]
+fn test_client(){
+    let client = GeometryClient::new(Client::new("http://127.0.0.1:1").unwrap());
+    assert_eq!(client.origin(), Err("RPC Request Failed."));
+}
+
+#[rpc]
+pub trait HTTPApi {
+    fn status(&self) -> Result<u16, &'static str>;
+}
+
+struct Server;
+
+impl HTTPApi for Server {
+    fn status(&self) -> Result<u16, &'static str> {
+        Ok(200)
+    }
+}
+
+#[test]
+fn test_register_acronym(){
+    let rpc = JsonRpc::new();
+    register_http_api(&rpc, Server);
+    let res = call(&rpc, "{\"jsonrpc\": \"2.0\", \"method\": \"status\", \"id\": 1}");
+    assert_eq!(&res, "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":200}");
+}
+
+#[derive(Debug, PartialEq, AppError)]
+pub enum WalletError {
+    #[app_error(code = 1, message = "Insufficient funds")]
+    InsufficientFunds,
+    #[app_error(code = -2, message = "Unknown account")]
+    UnknownAccount(String),
+}
+
+impl From<ClientError> for WalletError {
+    fn from(err: ClientError) -> WalletError {
+        err.app_error().unwrap_or(WalletError::UnknownAccount(err.to_string()))
+    }
+}
+
+#[rpc]
+pub trait Wallet {
+    fn withdraw(&self, account: String, amount: u64) -> Result<u64, WalletError>;
+}
+
+struct Accounts;
+
+impl Wallet for Accounts {
+    fn withdraw(&self, account: String, amount: u64) -> Result<u64, WalletError> {
+        match (account.as_ref(), amount) {
+            ("alice", 0 ..= 100) => Ok(100 - amount),
+            ("alice", _)         => Err(WalletError::InsufficientFunds),
+            _                    => Err(WalletError::UnknownAccount(account))
+        }
+    }
+}
+
+#[test]
+fn test_app_error(){
+    let mut rpc = JsonRpc::new();
+    register_wallet(&rpc, Accounts);
+    assert_eq!(rpc.register_errors::<WalletError>(), Ok(()));
+    assert_eq!(rpc.errors().get(&-2).map(|message| message.as_ref()), Some("Unknown account"));
+
+    let res = call(&rpc, "{\"jsonrpc\": \"2.0\", \"method\": \"withdraw\", \"params\": [\"alice\", 200], \"id\": 1}");
+    assert_eq!(&res, "{\"error\":{\"code\":1,\"data\":null,\"message\":\"Insufficient funds\"},\"id\":1,\"jsonrpc\":\"2.0\"}");
+    let res = call(&rpc, "{\"jsonrpc\": \"2.0\", \"method\": \"withdraw\", \"params\": [\"bob\", 1], \"id\": 2}");
+    assert_eq!(&res, "{\"error\":{\"code\":-2,\"data\":\"bob\",\"message\":\"Unknown account\"},\"id\":2,\"jsonrpc\":\"2.0\"}");
+
+    let remote = ClientError::Remote(Error::new(-2, "Unknown account", Some("bob".to_json())));
+    assert_eq!(remote.app_error(), Some(WalletError::UnknownAccount("bob".to_string())));
+    assert_eq!(ClientError::Remote(Error::new(1, "", None)).app_error(), Some(WalletError::InsufficientFunds));
+    assert_eq!(ClientError::Remote(Error::MethodNotFound).app_error::<WalletError>(), None);
+    assert_eq!(WalletError::from_error(&Error::new(-2, "Unknown account", Some(Json::I64(3)))), None);
+}
+
+struct Reserved;
+
+impl AppError for Reserved {
+    fn codes() -> Vec<(i64, &'static str)> {
+        vec![(-32001, "Busy"), (1, "Other funds"), (7, "Seven")]
+    }
+    fn code(&self) -> i64 {
+        -32001
+    }
+    fn message(&self) -> String {
+        "Busy".to_string()
+    }
+    fn from_error(_: &Error) -> Option<Reserved> {
+        None
+    }
+}
+
+struct Overdrawn;
+
+impl AppError for Overdrawn {
+    fn codes() -> Vec<(i64, &'static str)> {
+        vec![(1, "Overdrawn"), (3, "Frozen")]
+    }
+    fn code(&self) -> i64 {
+        1
+    }
+    fn message(&self) -> String {
+        "Overdrawn".to_string()
+    }
+    fn from_error(_: &Error) -> Option<Overdrawn> {
+        None
+    }
+}
+
+#[test]
+fn test_register_errors(){
+    let mut rpc = JsonRpc::new();
+    assert_eq!(rpc.register_errors::<WalletError>(), Ok(()));
+    assert_eq!(rpc.register_errors::<Reserved>(), Err(vec![-32001, 1]));
+    assert_eq!(rpc.errors().keys().collect::<Vec<&i64>>(), vec![&-2, &1]);
+    let doc = rpc.openrpc();
+    assert_eq!(doc.find_path(&["components", "errors", "1", "message"]), Some(&"Insufficient funds".to_json()));
+
+    // Mounting a router declaring code 1 differently is refused as well.
+    let mut bank = JsonRpc::new();
+    assert_eq!(bank.register_errors::<Overdrawn>(), Ok(()));
+    assert_eq!(rpc.mount("bank", ".", bank), Err(vec!["error 1".to_string()]));
+    assert_eq!(rpc.errors().get(&1).map(|message| message.as_ref()), Some("Insufficient funds"));
+    let mut bank = JsonRpc::new();
+    assert_eq!(bank.register_errors::<WalletError>(), Ok(()));
+    assert_eq!(rpc.mount("bank", ".", bank), Ok(()));
+}
@@ -0,0 +1,143 @@
+#![cfg(feature = "tracing")]
+
+extern crate hyper;
+extern crate jsonrpc2;
+extern crate tracing;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tracing::{Event, Metadata, Subscriber};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use jsonrpc2::{JsonRpc, HttpHandler, Client, Error, Json, ToJson};
+
+#[derive(Debug, Clone)]
+struct Span {
+    name  : &'static str,
+    parent: Option<u64>,
+    fields: BTreeMap<&'static str, String>
+}
+
+impl Visit for Span {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.fields.insert(field.name(), format!("{:?}", value));
+    }
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields.insert(field.name(), value.to_string());
+    }
+}
+
+thread_local! {
+    static ENTERED: RefCell<Vec<u64>> = RefCell::new(Vec::new());
+}
+
+/// Records every span with its fields and parent; span ids are indices
+/// into `spans`, plus one.
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<Span>>>
+}
+
+impl Recorder {
+    fn spans(&self) -> Vec<Span> {
+        self.spans.lock().unwrap().clone()
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+    fn new_span(&self, attributes: &Attributes) -> Id {
+        let parent = match attributes.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if attributes.is_contextual() => ENTERED.with(|entered| entered.borrow().last().cloned()),
+            None => None
+        };
+        let mut span = Span { name: attributes.metadata().name(), parent: parent, fields: BTreeMap::new() };
+        attributes.record(&mut span);
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(span);
+        Id::from_u64(spans.len() as u64)
+    }
+    fn record(&self, id: &Id, values: &Record) {
+        values.record(&mut self.spans.lock().unwrap()[id.into_u64() as usize - 1]);
+    }
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+    fn event(&self, _: &Event) {}
+    fn enter(&self, id: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(id.into_u64()));
+    }
+    fn exit(&self, _: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().pop());
+    }
+}
+
+fn find<'a>(spans: &'a [Span], name: &str, method: &str) -> &'a Span {
+    spans.iter()
+         .find(|span| span.name == name && span.fields.get("method").map(|m| m.as_str()) == Some(method))
+         .unwrap_or_else(|| panic!("no {} span of {} in {:?}", name, method, spans))
+}
+
+#[test]
+fn test_tracing(){
+    let recorder = Recorder::default();
+    tracing::subscriber::set_global_default(recorder.clone()).unwrap();
+
+    // A client calls `relay` on one service, which calls `echo` on another.
+    let echo = JsonRpc::new();
+    echo.register("echo", Box::new(|params: &Option<Json>| -> Result<Json, Error> {
+        Ok(params.clone().unwrap_or(Json::Null))
+    }));
+    let mut echo = hyper::Server::http("127.0.0.1:0").unwrap().handle(HttpHandler::new(Arc::new(echo))).unwrap();
+    let relay = JsonRpc::new();
+    let downstream = format!("http://{}", echo.socket);
+    relay.register("relay", Box::new(move |params: &Option<Json>| -> Result<Json, Error> {
+        let client = Client::new(&downstream).unwrap();
+        client.request("echo", params, &7).map(|result| result.unwrap_or(Json::Null)).map_err(|err| Error::from(err.to_string()))
+    }));
+    let mut relay = hyper::Server::http("127.0.0.1:0").unwrap().handle(HttpHandler::new(Arc::new(relay))).unwrap();
+    let client = Client::new(&format!("http://{}", relay.socket)).unwrap();
+    assert_eq!(client.request("relay", &Some(vec![1].to_json()), &1), Ok(Some(vec![1u64].to_json())));
+    assert!(client.request("missing", &None, &2).is_err());
+    echo.close().unwrap();
+    relay.close().unwrap();
+
+    let spans = recorder.spans();
+    let call    = find(&spans, "jsonrpc.client",  "relay");
+    let served  = find(&spans, "jsonrpc.request", "relay");
+    let forward = find(&spans, "jsonrpc.client",  "echo");
+    let echoed  = find(&spans, "jsonrpc.request", "echo");
+    // One trace across both services, each hop the child of the last.
+    for span in &[served, forward, echoed] {
+        assert_eq!(span.fields["trace_id"], call.fields["trace_id"]);
+    }
+    assert_eq!(call.fields.get("parent_id"), None);
+    assert_eq!(served.fields["parent_id"],  call.fields["span_id"]);
+    assert_eq!(forward.fields["parent_id"], served.fields["span_id"]);
+    assert_eq!(echoed.fields["parent_id"],  forward.fields["span_id"]);
+    // Within the relay, the call to echo is made in the span of the request.
+    assert_eq!(forward.parent.map(|id| &spans[id as usize - 1].fields["span_id"]), Some(&served.fields["span_id"]));
+    assert_eq!(served.fields["id"], "1");
+    assert_eq!(forward.fields["id"], "7");
+    assert_eq!(served.fields["outcome"], "success");
+    assert_eq!(call.fields["outcome"], "success");
+
+    let missing = find(&spans, "jsonrpc.request", "missing");
+    assert_eq!(missing.fields["outcome"], "error");
+    assert_eq!(missing.fields["code"], "-32601");
+    assert!(missing.fields["trace_id"] != call.fields["trace_id"]);
+    assert_eq!(find(&spans, "jsonrpc.client", "missing").fields["code"], "-32601");
+
+    // Without HTTP, the trace context travels in the request.
+    let rpc = JsonRpc::new();
+    rpc.register("local", Box::new(|_: &Option<Json>| -> Result<Json, Error> { Ok(Json::Null) }));
+    rpc.handle("{\"jsonrpc\": \"2.0\", \"method\": \"local\", \"id\": 3, \
+                 \"traceparent\": \"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01\"}");
+    let spans = recorder.spans();
+    let local = find(&spans, "jsonrpc.request", "local");
+    assert_eq!(local.fields["trace_id"], "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(local.fields["parent_id"], "00f067aa0ba902b7");
+}